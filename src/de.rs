@@ -1,22 +1,29 @@
 //! Deserialization support for the `application/x-www-form-urlencoded` format.
 
+use crate::encoding::{split_pair, unescape};
+use crate::{Charset, SpaceEncoding};
 use form_urlencoded::parse;
 use form_urlencoded::Parse as UrlEncodedParse;
+use percent_encoding::percent_decode;
 use serde::de::value::MapDeserializer;
-use serde::de::Error as de_Error;
 use serde::de::{self, IntoDeserializer};
 use serde::forward_to_deserialize_any;
 use std::borrow::Cow;
-use std::collections::btree_map::{self, BTreeMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io::Read;
+use std::iter;
 use std::mem;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::vec;
 
 #[doc(inline)]
-pub use serde::de::value::Error;
+pub use crate::error::{Error, ErrorKind};
 
 mod val_or_vec;
 
-use val_or_vec::ValOrVec;
+use val_or_vec::{Elements, Grouped, Map, Segment, ValOrVec};
 
 /// Deserializes a `application/x-www-form-urlencoded` value from a `&[u8]`.
 ///
@@ -37,7 +44,7 @@ pub fn from_bytes<'de, T>(input: &'de [u8]) -> Result<T, Error>
 where
     T: de::Deserialize<'de>,
 {
-    T::deserialize(Deserializer::new(parse(input)))
+    T::deserialize(Deserializer::from_bytes(input))
 }
 
 /// Deserializes a `application/x-www-form-urlencoded` value from a `&str`.
@@ -70,32 +77,743 @@ where
     R: Read,
 {
     let mut buf = vec![];
-    reader.read_to_end(&mut buf).map_err(|e| {
-        de::Error::custom(format_args!("could not read input: {}", e))
-    })?;
+    reader.read_to_end(&mut buf).map_err(Error::io)?;
     from_bytes(&buf)
 }
 
+/// Like `from_reader`, but stops reading and fails as soon as the input
+/// exceeds `limits.max_input_bytes`, and enforces the other limits while
+/// deserializing.
+///
+/// ```
+/// use serde_urlencoded::de::Limits;
+///
+/// let limits = Limits::new().max_input_bytes(16).max_pairs(2);
+///
+/// assert_eq!(
+///     serde_urlencoded::de::from_reader_with_limits::<Vec<(String, u32)>, _>(
+///         &b"a=1&b=2"[..], limits),
+///     Ok(vec![("a".to_owned(), 1), ("b".to_owned(), 2)]));
+/// assert!(
+///     serde_urlencoded::de::from_reader_with_limits::<Vec<(String, u32)>, _>(
+///         &b"a=1&b=2&c=3"[..], limits).is_err());
+/// ```
+pub fn from_reader_with_limits<T, R>(
+    reader: R,
+    limits: Limits,
+) -> Result<T, Error>
+where
+    T: de::DeserializeOwned,
+    R: Read,
+{
+    let mut buf = vec![];
+    let mut reader = match limits.max_input_bytes {
+        // Read one byte past the limit to tell whether it was exceeded.
+        Some(max) => reader.take((max as u64).saturating_add(1)),
+        None => reader.take(u64::MAX),
+    };
+    reader.read_to_end(&mut buf).map_err(Error::io)?;
+    T::deserialize(Deserializer::from_bytes(&buf).limits(limits))
+}
+
+/// Limits on the input accepted by a `Deserializer`, to bound the memory
+/// used when deserializing untrusted input.
+///
+/// Only the nesting depth of keys is limited by default, to 32 levels, as
+/// nested keys are deserialized recursively. When a limit is exceeded,
+/// deserialization stops and fails with an error of kind
+/// `ErrorKind::LimitExceeded`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    max_input_bytes: Option<usize>,
+    max_pairs: Option<usize>,
+    max_key_len: Option<usize>,
+    max_value_len: Option<usize>,
+    max_sequence_len: Option<usize>,
+    max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_input_bytes: None,
+            max_pairs: None,
+            max_key_len: None,
+            max_value_len: None,
+            max_sequence_len: None,
+            max_depth: 32,
+        }
+    }
+}
+
+impl Limits {
+    /// Returns the default limits, with only the nesting depth of keys
+    /// limited.
+    pub fn new() -> Self {
+        Limits::default()
+    }
+
+    /// Sets the maximum length of the input in bytes.
+    ///
+    /// This is only checked when the input bytes are known, that is, with
+    /// `Deserializer::from_bytes` and `from_reader_with_limits`.
+    pub fn max_input_bytes(mut self, limit: usize) -> Self {
+        self.max_input_bytes = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of pairs in the input.
+    pub fn max_pairs(mut self, limit: usize) -> Self {
+        self.max_pairs = Some(limit);
+        self
+    }
+
+    /// Sets the maximum length in bytes of a decoded key.
+    pub fn max_key_len(mut self, limit: usize) -> Self {
+        self.max_key_len = Some(limit);
+        self
+    }
+
+    /// Sets the maximum length in bytes of a decoded value.
+    pub fn max_value_len(mut self, limit: usize) -> Self {
+        self.max_value_len = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of elements collected into a single sequence
+    /// or nested map, or split from a value by `Deserializer::list_delimiter`.
+    pub fn max_sequence_len(mut self, limit: usize) -> Self {
+        self.max_sequence_len = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of bracketed segments of a key, such as 2
+    /// for `user[address][city]`, which defaults to 32.
+    pub fn max_depth(mut self, limit: usize) -> Self {
+        self.max_depth = limit;
+        self
+    }
+}
+
+/// How a `Deserializer` handles a key that appears more than once, as in
+/// `a=1&a=2`, or with conflicting shapes, as in `a=1&a[]=2` or
+/// `a[]=1&a[b]=2`.
+///
+/// Elements of sequences such as `a[]=1&a[]=2` are never duplicates, and
+/// neither are repeated plain keys when `Deserializer::repeated_keys` is
+/// enabled. The policy applies at every level of nested keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Keeps every value, leaving duplicates to the deserialized type: a
+    /// struct rejects a duplicate field, a map keeps the last value and a
+    /// sequence of pairs keeps them all.
+    #[default]
+    KeepAll,
+    /// Keeps the first value for each key.
+    First,
+    /// Keeps the last value for each key, at the position of the first one.
+    Last,
+    /// Fails with an error of kind `ErrorKind::DuplicateKey` naming the
+    /// duplicate key.
+    Reject,
+}
+
+/// How a `Deserializer` handles gaps between the indices of a sequence, as
+/// in `tag[0]=a&tag[2]=b`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SequenceGaps {
+    /// Skips the gaps, keeping the elements in the order of their indices,
+    /// the default.
+    #[default]
+    Compact,
+    /// Fails with an error of kind `ErrorKind::Malformed` naming the first
+    /// missing index, unless the indices go from `0` to the length of the
+    /// sequence minus one.
+    Reject,
+}
+
+/// How a `Deserializer` matches the names of enum variants given as values,
+/// as in `status=active`, or as the tag of a top-level enum.
+///
+/// A name that matches a variant exactly is always that variant, otherwise
+/// it is the first matching variant in declaration order. A name matching
+/// no variant is reported as given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VariantMatching {
+    /// Names must match exactly, the default.
+    #[default]
+    Exact,
+    /// Names match regardless of ASCII case, so that `Active` and `ACTIVE`
+    /// match a variant named `active`.
+    IgnoreCase,
+    /// Names match regardless of ASCII case and of `-` and `_`, so that
+    /// `In-Progress` matches a variant named `in_progress`.
+    IgnoreCaseAndSeparators,
+}
+
+impl VariantMatching {
+    /// Returns the name of the variant matching `name`, or `name` itself if
+    /// there is none.
+    fn resolve<'de>(
+        self,
+        name: Cow<'de, str>,
+        variants: &'static [&'static str],
+    ) -> Cow<'de, str> {
+        if self == VariantMatching::Exact || variants.contains(&&*name) {
+            return name;
+        }
+        let found = variants.iter().find(|variant| match self {
+            VariantMatching::IgnoreCaseAndSeparators => {
+                fold_variant(variant).eq(fold_variant(&name))
+            }
+            _ => variant.eq_ignore_ascii_case(&name),
+        });
+        match found {
+            Some(variant) => Cow::Borrowed(variant),
+            None => name,
+        }
+    }
+}
+
+/// Lowercases `name` and strips its separators, for
+/// `VariantMatching::IgnoreCaseAndSeparators`.
+fn fold_variant(name: &str) -> impl Iterator<Item = char> + '_ {
+    name.chars()
+        .filter(|&c| c != '-' && c != '_')
+        .map(|c| c.to_ascii_lowercase())
+}
+
+fn check_limit(
+    what: &str,
+    value: usize,
+    limit: Option<usize>,
+) -> Result<(), Error> {
+    match limit {
+        Some(limit) if value > limit => Err(Error::new(
+            ErrorKind::LimitExceeded,
+            format_args!("limit exceeded: {} (limit is {})", what, limit),
+        )),
+        _ => Ok(()),
+    }
+}
+
 /// A deserializer for the `application/x-www-form-urlencoded` format.
 ///
 /// * Supported top-level outputs are structs, maps and sequences of pairs,
-///   with or without a given length.
+///   with or without a given length. Sequences of structs or maps are read
+///   from indexed groups such as `0[name]=x&0[qty]=2`.
+///
+/// * Keys such as `user[address][city]` are grouped into nested maps, which
+///   can be deserialized as structs or maps of any depth, and keys ending
+///   with `[]` are collected into sequences. Keys with numeric segments such
+///   as `items[0]` can also be deserialized as sequences, ordered by index:
+///   duplicate indices are rejected, and gaps between indices are skipped
+///   unless `Deserializer::sequence_gaps` says otherwise. Keys nested deeper
+///   than `Limits::max_depth` are rejected.
+///
+/// * Top-level keys that are deserialized as strings rather than as fields,
+///   such as the keys of a `HashMap<String, String>`, are read as they are
+///   in the input, `filter[name]` rather than `filter`, with the pairs of a
+///   group at the position of its first pair.
+///
+/// * Pairs are visited in the order of the input, with each sequence or map
+///   at the position of its first key.
+///
+/// * Bytes, such as `serde_bytes` fields, are the percent-decoded bytes of
+///   the value, even if they are not valid UTF-8, except with
+///   `Deserializer::new` which only has the value decoded as text.
+///
+/// * Top-level enums are read from a tag pair naming the variant, such as
+///   `event=push&ref=main`, with the remaining pairs as the fields of a
+///   struct variant or the inner value of a newtype variant, see
+///   `Deserializer::enum_tag`.
 ///
 /// * Main `deserialize` methods defers to `deserialize_map`.
 ///
-/// * Everything else but `deserialize_seq` and `deserialize_seq_fixed_size`
-///   defers to `deserialize`.
+/// * Everything else but `deserialize_seq`, `deserialize_seq_fixed_size`
+///   and `deserialize_enum` defers to `deserialize`.
 pub struct Deserializer<'de> {
-    inner: MapDeserializer<'de, PartIterator<'de>, Error>,
+    parts: PartIterator<'de>,
+    enum_tag: &'static str,
 }
 
 impl<'de> Deserializer<'de> {
     /// Returns a new `Deserializer`.
     pub fn new(parse: UrlEncodedParse<'de>) -> Self {
         Deserializer {
-            inner: MapDeserializer::new(PartIterator::new(parse)),
+            parts: PartIterator::new(Input::Parse(parse)),
+            enum_tag: "event",
+        }
+    }
+
+    /// Returns a new `Deserializer` parsing `input`.
+    ///
+    /// Unlike with `Deserializer::new`, the errors of this deserializer have
+    /// the byte range of the pair that failed in `input`, see `Error::span`.
+    pub fn from_bytes(input: &'de [u8]) -> Self {
+        let mut parts = PartIterator::new(Input::Bytes { input, position: 0 });
+        parts.input_len = Some(input.len());
+        Deserializer {
+            parts,
+            enum_tag: "event",
         }
     }
+
+    /// Deserializes a `T`, collecting every value that fails to parse
+    /// instead of stopping at the first one.
+    ///
+    /// A value that is not a valid number or boolean is recorded, with the
+    /// key of its pair, and replaced by one or `false` so that the remaining
+    /// pairs are still deserialized. One rather than zero is accepted by
+    /// types such as `NonZeroU32`. All the recorded errors are returned
+    /// together, in the order of the input, followed by the error that
+    /// stopped deserialization if there is one, such as a missing field,
+    /// unless it is about a pair whose error was already recorded, as when
+    /// the type of a value rejects its placeholder.
+    ///
+    /// Only numbers and booleans are collected: any other error, such as an
+    /// unknown enum variant or a value of the wrong shape, stops
+    /// deserialization. The collected errors have the full key, the value
+    /// and the span of their pair, such as `address[zip]`, but no `path`,
+    /// since a path is built while an error is returned through the nested
+    /// values and collected errors are not returned.
+    ///
+    /// ```
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// #[derive(Debug, serde_derive::Deserialize)]
+    /// struct Form {
+    ///     name: String,
+    ///     age: u8,
+    ///     height: f32,
+    ///     subscribed: bool,
+    /// }
+    ///
+    /// let de = Deserializer::from_bytes(
+    ///     b"name=Ann&age=old&height=tall&subscribed=true");
+    /// let errors = de.deserialize_collecting_errors::<Form>().unwrap_err();
+    ///
+    /// let keys = errors.iter().map(|e| e.key().unwrap()).collect::<Vec<_>>();
+    /// assert_eq!(keys, ["age", "height"]);
+    /// assert_eq!(errors[0].path(), None);
+    /// ```
+    pub fn deserialize_collecting_errors<T>(mut self) -> Result<T, Vec<Error>>
+    where
+        T: de::Deserialize<'de>,
+    {
+        let errors = Arc::new(Mutex::new(vec![]));
+        self.parts.errors = Some(errors.clone());
+        let result = T::deserialize(self);
+        let mut errors = mem::take(&mut *errors.lock().unwrap());
+        match result {
+            Ok(value) if errors.is_empty() => Ok(value),
+            Ok(_) => Err(errors),
+            Err(err) => {
+                let recorded = errors.iter().any(|e| {
+                    e.key() == err.key()
+                        && (err.span().is_none() || e.span() == err.span())
+                });
+                if !recorded {
+                    errors.push(err);
+                }
+                Err(errors)
+            }
+        }
+    }
+
+    /// Decodes keys and values with `charset` instead of UTF-8.
+    ///
+    /// This requires a deserializer created with `from_bytes`, as
+    /// `form_urlencoded::Parse` always decodes UTF-8: with any other charset,
+    /// a deserializer created with `Deserializer::new` fails with an error
+    /// of kind `ErrorKind::Unsupported`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::{Charset, Deserializer};
+    ///
+    /// let charset = Charset::for_label("ISO-8859-1").unwrap();
+    /// let de = Deserializer::from_bytes(b"cheese=comt%E9").charset(charset);
+    ///
+    /// assert_eq!(
+    ///     Vec::<(String, String)>::deserialize(de),
+    ///     Ok(vec![("cheese".to_owned(), "comté".to_owned())]));
+    /// ```
+    pub fn charset(mut self, charset: Charset) -> Self {
+        self.parts.decoding.charset = charset;
+        self
+    }
+
+    /// Decodes keys and values with the charset named by the value of the
+    /// first `_charset_` pair of the input, if any, as specified by HTML for
+    /// form submissions. The `_charset_` pair itself is kept.
+    ///
+    /// This takes precedence over `Deserializer::charset`, which is used
+    /// when the input has no such pair. Like it, enabling this on a
+    /// deserializer created with `Deserializer::new` makes deserialization
+    /// fail with an error of kind `ErrorKind::Unsupported`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// struct Form {
+    ///     name: String,
+    /// }
+    ///
+    /// let de = Deserializer::from_bytes(b"_charset_=Shift_JIS&name=%93%FA")
+    ///     .use_charset_field(true);
+    ///
+    /// assert_eq!(
+    ///     Form::deserialize(de),
+    ///     Ok(Form { name: "日".to_owned() }));
+    /// ```
+    pub fn use_charset_field(mut self, enabled: bool) -> Self {
+        self.parts.decoding.charset_field = enabled;
+        self
+    }
+
+    /// Fails with an error of kind `ErrorKind::Utf8` when a key or value is
+    /// not valid UTF-8 once percent-decoded, instead of replacing invalid
+    /// sequences with U+FFFD.
+    ///
+    /// The error has the key of the pair and, through `Error::source`, the
+    /// `Utf8Error` giving the offset of the invalid sequence in the decoded
+    /// key or value. This only applies to deserializers created with
+    /// `from_bytes`, when decoding UTF-8: `form_urlencoded::Parse` always
+    /// replaces invalid sequences, so enabling it on a deserializer created
+    /// with `Deserializer::new` makes deserialization fail with an error of
+    /// kind `ErrorKind::Unsupported`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::de::{Deserializer, ErrorKind};
+    ///
+    /// let de = Deserializer::from_bytes(b"name=caf%E9").strict_utf8(true);
+    /// let err = Vec::<(String, String)>::deserialize(de).unwrap_err();
+    ///
+    /// assert_eq!(err.kind(), ErrorKind::Utf8);
+    /// assert_eq!(err.key(), Some("name"));
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "invalid UTF-8 in value: \
+    ///      incomplete utf-8 byte sequence from index 3");
+    /// ```
+    pub fn strict_utf8(mut self, enabled: bool) -> Self {
+        self.parts.decoding.strict_utf8 = enabled;
+        self
+    }
+
+    /// Deserializes values that look like booleans or numbers as such when
+    /// the type of the value is not known, which makes `#[serde(flatten)]`,
+    /// untagged enums and internally tagged enums work with fields that
+    /// are not strings.
+    ///
+    /// Serde buffers the values of such fields with `deserialize_any` before
+    /// knowing their type, and cannot parse a buffered string into a number
+    /// afterwards. With this option, `true` and `false` are then booleans,
+    /// and integers and finite floats written as Rust would format them,
+    /// such as `-12` or `2.5` but not `007` or `1e3`, are numbers. Buffered
+    /// `String` fields then cannot hold such values, which is why the option
+    /// is disabled by default.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// struct Pagination {
+    ///     page: u32,
+    ///     per_page: u32,
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// struct Search {
+    ///     q: String,
+    ///     #[serde(flatten)]
+    ///     pagination: Pagination,
+    /// }
+    ///
+    /// let input = b"q=rust&page=2&per_page=20";
+    /// let de = Deserializer::from_bytes(input).infer_types(true);
+    /// assert_eq!(
+    ///     Search::deserialize(de),
+    ///     Ok(Search {
+    ///         q: "rust".to_owned(),
+    ///         pagination: Pagination { page: 2, per_page: 20 },
+    ///     }));
+    ///
+    /// let de = Deserializer::from_bytes(input);
+    /// assert!(Search::deserialize(de).is_err());
+    /// ```
+    pub fn infer_types(mut self, enabled: bool) -> Self {
+        self.parts.options.infer_types = enabled;
+        self
+    }
+
+    /// Sets whether `+` decodes as a space, which it does by default, or as
+    /// a plus sign, see `SpaceEncoding`.
+    ///
+    /// `form_urlencoded::Parse` always decodes `+` as a space, so a
+    /// deserializer created with `Deserializer::new` fails with an error of
+    /// kind `ErrorKind::Unsupported` with `SpaceEncoding::Percent`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::{Deserializer, SpaceEncoding};
+    ///
+    /// let de = Deserializer::from_bytes(b"q=1+1%20is%202")
+    ///     .spaces(SpaceEncoding::Percent);
+    ///
+    /// assert_eq!(
+    ///     Vec::<(String, String)>::deserialize(de),
+    ///     Ok(vec![("q".to_owned(), "1+1 is 2".to_owned())]));
+    /// ```
+    pub fn spaces(mut self, spaces: SpaceEncoding) -> Self {
+        self.parts.options.spaces = spaces;
+        self
+    }
+
+    /// Fails with an error of kind `ErrorKind::Malformed` when a pair has a
+    /// malformed percent-escape such as `%zz` or a trailing `%`, a raw
+    /// non-ASCII byte or an ASCII control character, raw or percent-encoded
+    /// such as `%00`, instead of passing them through as they are. Tabs and
+    /// line breaks are still accepted when percent-encoded, as `%09`, `%0A`
+    /// and `%0D`, since that is how the value of a `textarea` is sent.
+    ///
+    /// The error has the key of the pair, and its message has the offset of
+    /// the offending byte in the input. This only applies to deserializers
+    /// created with `from_bytes`: `form_urlencoded::Parse` only yields the
+    /// decoded pairs, so enabling it on a deserializer created with
+    /// `Deserializer::new` makes deserialization fail with an error of kind
+    /// `ErrorKind::Unsupported`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::de::{Deserializer, ErrorKind};
+    ///
+    /// let de = Deserializer::from_bytes(b"a=1&b=50%zz").strict_encoding(true);
+    /// let err = Vec::<(String, String)>::deserialize(de).unwrap_err();
+    ///
+    /// assert_eq!(err.kind(), ErrorKind::Malformed);
+    /// assert_eq!(err.key(), Some("b"));
+    /// assert_eq!(err.to_string(), "malformed percent-escape `%zz` at byte 8");
+    /// ```
+    pub fn strict_encoding(mut self, enabled: bool) -> Self {
+        self.parts.decoding.strict_encoding = enabled;
+        self
+    }
+
+    /// Sets how duplicate keys are handled, see `DuplicateKeys`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::de::{Deserializer, DuplicateKeys};
+    ///
+    /// let de = Deserializer::from_bytes(b"a=1&b=2&a=3")
+    ///     .duplicate_keys(DuplicateKeys::Last);
+    /// assert_eq!(
+    ///     Vec::<(String, u32)>::deserialize(de),
+    ///     Ok(vec![("a".to_owned(), 3), ("b".to_owned(), 2)]));
+    ///
+    /// let de = Deserializer::from_bytes(b"a=1&a[]=2")
+    ///     .duplicate_keys(DuplicateKeys::Reject);
+    /// assert_eq!(
+    ///     Vec::<(String, Vec<u32>)>::deserialize(de)
+    ///         .unwrap_err()
+    ///         .to_string(),
+    ///     "duplicate key `a[]`");
+    /// ```
+    pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.parts.duplicate_keys = policy;
+        self
+    }
+
+    /// Sets the limits on the input, see `Limits`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::de::{Deserializer, ErrorKind, Limits};
+    ///
+    /// let de = Deserializer::from_bytes(b"tags[]=a&tags[]=b&tags[]=c")
+    ///     .limits(Limits::new().max_sequence_len(2));
+    ///
+    /// let err = Vec::<(String, Vec<String>)>::deserialize(de).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    /// ```
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.parts.limits = limits;
+        self.parts.options.max_sequence_len = limits.max_sequence_len;
+        self
+    }
+
+    /// Collects the values of repeated plain keys such as `tag=a&tag=b` into
+    /// sequences, so that they can be deserialized into `Vec<T>` fields
+    /// without the `[]` suffix. A plain key that appears only once can then
+    /// also be deserialized as a sequence of one element.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// struct Filter {
+    ///     tag: Vec<String>,
+    ///     page: u32,
+    /// }
+    ///
+    /// let input = form_urlencoded::parse(b"tag=a&page=2&tag=b");
+    /// let de = Deserializer::new(input).repeated_keys(true);
+    ///
+    /// assert_eq!(
+    ///     Filter::deserialize(de),
+    ///     Ok(Filter {
+    ///         tag: vec!["a".to_owned(), "b".to_owned()],
+    ///         page: 2,
+    ///     }));
+    /// ```
+    pub fn repeated_keys(mut self, enabled: bool) -> Self {
+        self.parts.options.repeated_keys = enabled;
+        self
+    }
+
+    /// Sets how gaps between the indices of sequences such as
+    /// `tag[0]=a&tag[2]=b` are handled, which defaults to
+    /// `SequenceGaps::Compact`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::de::{Deserializer, ErrorKind, SequenceGaps};
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// struct Tags {
+    ///     tag: Vec<String>,
+    /// }
+    ///
+    /// let input = form_urlencoded::parse(b"tag[5]=a&tag[1]=b");
+    /// let de = Deserializer::new(input);
+    /// assert_eq!(
+    ///     Tags::deserialize(de),
+    ///     Ok(Tags { tag: vec!["b".to_owned(), "a".to_owned()] }));
+    ///
+    /// let input = form_urlencoded::parse(b"tag[5]=a&tag[1]=b");
+    /// let de = Deserializer::new(input).sequence_gaps(SequenceGaps::Reject);
+    /// let err = Tags::deserialize(de).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::Malformed);
+    /// ```
+    pub fn sequence_gaps(mut self, gaps: SequenceGaps) -> Self {
+        self.parts.options.sequence_gaps = gaps;
+        self
+    }
+
+    /// Splits values on `delimiter` when they are deserialized as sequences,
+    /// so that `ids=1,2,3` can be deserialized into a `Vec<u32>` field. Each
+    /// piece is parsed just like a single value, and an empty value is an
+    /// empty sequence.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// let input = form_urlencoded::parse(b"ids=1,2,3");
+    /// let de = Deserializer::new(input).list_delimiter(',');
+    ///
+    /// assert_eq!(
+    ///     Vec::<(String, Vec<u32>)>::deserialize(de),
+    ///     Ok(vec![("ids".to_owned(), vec![1, 2, 3])]));
+    /// ```
+    pub fn list_delimiter(mut self, delimiter: char) -> Self {
+        self.parts.options.list_delimiter = Some(delimiter);
+        self
+    }
+
+    /// Sets the key of the pair naming the variant of a top-level enum,
+    /// which defaults to `event`. The pair can be anywhere in the input, but
+    /// only once, unless `Deserializer::duplicate_keys` keeps a single one.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// #[serde(rename_all = "snake_case")]
+    /// enum Action {
+    ///     Rename { name: String },
+    ///     Delete,
+    /// }
+    ///
+    /// let input = form_urlencoded::parse(b"name=notes&action=rename");
+    /// let de = Deserializer::new(input).enum_tag("action");
+    ///
+    /// assert_eq!(
+    ///     Action::deserialize(de),
+    ///     Ok(Action::Rename { name: "notes".to_owned() }));
+    /// ```
+    pub fn enum_tag(mut self, tag: &'static str) -> Self {
+        self.enum_tag = tag;
+        self
+    }
+
+    /// Sets how the names of enum variants given as values or as the tag
+    /// of a top-level enum are matched, which defaults to
+    /// `VariantMatching::Exact`.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::de::{Deserializer, VariantMatching};
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// #[serde(rename_all = "snake_case")]
+    /// enum Status {
+    ///     Open,
+    ///     InProgress,
+    /// }
+    ///
+    /// let input = form_urlencoded::parse(b"a=Open&b=in-progress");
+    /// let de = Deserializer::new(input)
+    ///     .variant_matching(VariantMatching::IgnoreCaseAndSeparators);
+    ///
+    /// assert_eq!(
+    ///     Vec::<(String, Status)>::deserialize(de),
+    ///     Ok(vec![
+    ///         ("a".to_owned(), Status::Open),
+    ///         ("b".to_owned(), Status::InProgress),
+    ///     ]));
+    /// ```
+    pub fn variant_matching(mut self, matching: VariantMatching) -> Self {
+        self.parts.options.variant_matching = matching;
+        self
+    }
+
+    /// Lets a value made of digits only, such as `status=2`, select a unit
+    /// variant by its index in the enum, as sent by clients using numeric
+    /// codes. A variant named after the value still takes precedence.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_urlencoded::Deserializer;
+    ///
+    /// #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    /// enum Status {
+    ///     Draft,
+    ///     Published,
+    /// }
+    ///
+    /// let input = form_urlencoded::parse(b"a=1&b=Draft");
+    /// let de = Deserializer::new(input).variant_indices(true);
+    ///
+    /// assert_eq!(
+    ///     Vec::<(String, Status)>::deserialize(de),
+    ///     Ok(vec![
+    ///         ("a".to_owned(), Status::Published),
+    ///         ("b".to_owned(), Status::Draft),
+    ///     ]));
+    /// ```
+    pub fn variant_indices(mut self, enabled: bool) -> Self {
+        self.parts.options.variant_indices = enabled;
+        self
+    }
 }
 
 impl<'de> de::Deserializer<'de> for Deserializer<'de> {
@@ -108,28 +826,188 @@ impl<'de> de::Deserializer<'de> for Deserializer<'de> {
         self.deserialize_map(visitor)
     }
 
-    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_map(self.inner)
+        let result = visitor.visit_map(Pairs::new(self.parts.by_ref()));
+        self.parts.finish(result)
     }
 
-    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(self.inner)
+        let entries = self.parts.by_ref().collect::<Vec<_>>();
+        self.parts.finish(Ok(()))?;
+        let indexed = !entries.is_empty()
+            && entries.iter().all(|(key, value)| {
+                key.value.parse::<usize>().is_ok()
+                    && matches!(value, ValOrVec::Map(_))
+            });
+        if indexed {
+            // Indexed groups such as `0[name]=x&0[qty]=2` form a sequence of
+            // structs or maps.
+            ValOrVec::Map(entries.into_iter().collect())
+                .deserialize_seq(visitor)
+        } else {
+            visitor.visit_seq(Pairs::new(entries.into_iter()))
+        }
     }
 
-    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_unit<V>(
+        mut self,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        self.inner.end()?;
+        let result = MapDeserializer::new(&mut self.parts).end();
+        self.parts.finish(result)?;
         visitor.visit_unit()
     }
 
+    fn deserialize_enum<V>(
+        mut self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let mut fields = self.parts.by_ref().collect::<Vec<_>>();
+        self.parts.finish(Ok(()))?;
+        let tag = self.enum_tag;
+        match fields.iter().position(|(key, _)| key.value == tag) {
+            Some(index) => {
+                let (key, mut value) = fields.remove(index);
+                // The variant would otherwise depend on which pair is read.
+                let duplicate = fields.iter().find(|(key, _)| key.value == tag);
+                if duplicate.is_some() || matches!(value, ValOrVec::Vec(_)) {
+                    let message = format_args!("duplicate key `{}`", tag);
+                    let span = duplicate.and_then(|(key, _)| key.span.clone());
+                    return Err(Error::new(ErrorKind::DuplicateKey, message)
+                        .at(tag, None)
+                        .at_span(span.or_else(|| key.span.clone())));
+                }
+                if let ValOrVec::Val(ref mut part) = value {
+                    let matching = part.options.variant_matching;
+                    let name = mem::take(&mut part.value);
+                    part.value = matching.resolve(name, variants);
+                }
+                let (_, variant) = PairValue::new((key, value));
+                visitor.visit_enum(TaggedEnum { variant, fields })
+            }
+            None => Err(de::Error::missing_field(tag)),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool
+        u8
+        u16
+        u32
+        u64
+        i8
+        i16
+        i32
+        i64
+        f32
+        f64
+        char
+        str
+        string
+        option
+        bytes
+        byte_buf
+        unit_struct
+        newtype_struct
+        tuple_struct
+        struct
+        identifier
+        tuple
+        ignored_any
+    }
+}
+
+/// A top-level enum, with the value of its tag pair and the other pairs of
+/// the input.
+struct TaggedEnum<'de> {
+    variant: PairValue<'de>,
+    fields: Vec<(Part<'de>, ValOrVec<Part<'de>>)>,
+}
+
+impl<'de> de::EnumAccess<'de> for TaggedEnum<'de> {
+    type Error = Error;
+    type Variant = VariantFields<'de>;
+
+    fn variant_seed<S>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Self::Variant), Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.variant)?;
+        Ok((variant, VariantFields(self.fields)))
+    }
+}
+
+/// The pairs of the input other than the tag pair of a top-level enum.
+struct VariantFields<'de>(Vec<(Part<'de>, ValOrVec<Part<'de>>)>);
+
+impl<'de> de::VariantAccess<'de> for VariantFields<'de> {
+    type Error = Error;
+
+    /// Ignores the other pairs, as a struct ignores unknown fields.
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::TupleVariant,
+            &"unit, newtype or struct variant",
+        ))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for VariantFields<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_map(Pairs::new(self.0.into_iter()))
+    }
+
     forward_to_deserialize_any! {
         bool
         u8
@@ -148,9 +1026,12 @@ impl<'de> de::Deserializer<'de> for Deserializer<'de> {
         option
         bytes
         byte_buf
+        unit
         unit_struct
         newtype_struct
+        seq
         tuple_struct
+        map
         struct
         identifier
         tuple
@@ -159,65 +1040,927 @@ impl<'de> de::Deserializer<'de> for Deserializer<'de> {
     }
 }
 
+/// Options applying to every part of the input.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Options {
+    repeated_keys: bool,
+    sequence_gaps: SequenceGaps,
+    list_delimiter: Option<char>,
+    spaces: SpaceEncoding,
+    infer_types: bool,
+    variant_matching: VariantMatching,
+    variant_indices: bool,
+    /// `Limits::max_sequence_len`, for the values split on `list_delimiter`.
+    max_sequence_len: Option<usize>,
+}
+
+/// The pairs of the input, with their byte range in the input when it is
+/// known.
+enum Input<'de> {
+    Parse(UrlEncodedParse<'de>),
+    Bytes { input: &'de [u8], position: usize },
+}
+
+/// The options of the decoding of the input, which are only honoured by
+/// deserializers created with `from_bytes`.
+#[derive(Default)]
+struct Decoding {
+    charset: Charset,
+    /// Whether to look for a `_charset_` pair before the first pair.
+    charset_field: bool,
+    strict_utf8: bool,
+    strict_encoding: bool,
+}
+
+impl Decoding {
+    /// Fails for the first option set that a `form_urlencoded::Parse`
+    /// input cannot honour, as it only yields pairs decoded as UTF-8 with
+    /// `+` as a space.
+    fn check_parse(&self, options: Options) -> Result<(), Error> {
+        let option = if !self.charset.is_utf8() {
+            "charset"
+        } else if self.charset_field {
+            "use_charset_field"
+        } else if self.strict_utf8 {
+            "strict_utf8"
+        } else if self.strict_encoding {
+            "strict_encoding"
+        } else if options.spaces != SpaceEncoding::Plus {
+            "spaces"
+        } else {
+            return Ok(());
+        };
+        let message = format_args!(
+            "`{}` requires a deserializer created with `from_bytes`",
+            option
+        );
+        Err(Error::new(ErrorKind::Unsupported, message))
+    }
+}
+
+impl<'de> Input<'de> {
+    fn next(
+        &mut self,
+        options: Options,
+        decoding: &mut Decoding,
+    ) -> Option<Result<InputPair<'de>, Error>> {
+        match *self {
+            Input::Parse(ref mut parse) => {
+                if let Err(err) = decoding.check_parse(options) {
+                    return Some(Err(err));
+                }
+                parse.next().map(|(k, v)| Ok((k, v, None, None)))
+            }
+            Input::Bytes {
+                input,
+                ref mut position,
+            } => {
+                let Decoding {
+                    ref mut charset,
+                    ref mut charset_field,
+                    strict_utf8,
+                    strict_encoding,
+                } = *decoding;
+                if mem::take(charset_field) {
+                    if let Some(field) = Charset::from_charset_field(input) {
+                        *charset = field;
+                    }
+                }
+                // Split the input like `form_urlencoded::parse` does, to keep
+                // track of where each pair is.
+                while *position < input.len() {
+                    let start = *position;
+                    let end = input[start..]
+                        .iter()
+                        .position(|&b| b == b'&')
+                        .map_or(input.len(), |len| start + len);
+                    *position = end + 1;
+                    let segment = &input[start..end];
+                    let span = start..end;
+                    if strict_encoding {
+                        if let Err(err) = check_encoding(segment, start) {
+                            let key = parse(segment).next().map(|(k, _)| k);
+                            let err = err.at(&key.unwrap_or_default(), None);
+                            return Some(Err(err.at_span(Some(span))));
+                        }
+                    }
+                    let spaces = options.spaces;
+                    let pair = if strict_utf8 && charset.is_utf8() {
+                        parse_utf8_strict(segment, spaces)
+                    } else if charset.is_utf8() && spaces == SpaceEncoding::Plus
+                    {
+                        parse(segment).next().map(Ok)
+                    } else {
+                        charset.decode_pair(segment, spaces).map(Ok)
+                    };
+                    let raw = split_pair(segment).map(|(_, raw)| raw);
+                    match pair {
+                        Some(Ok((k, v))) => {
+                            return Some(Ok((k, v, raw, Some(span))))
+                        }
+                        Some(Err(err)) => {
+                            return Some(Err(err.at_span(Some(span))))
+                        }
+                        None => {}
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// A decoded key and value.
+type Pair<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+/// A decoded key and value, with the raw bytes of the value and the byte
+/// range of the pair in the input when they are known.
+type InputPair<'de> = (
+    Cow<'de, str>,
+    Cow<'de, str>,
+    Option<&'de [u8]>,
+    Option<Range<usize>>,
+);
+
+/// Checks that a pair only has well-formed percent-escapes and printable
+/// ASCII characters, encoded or not, or encoded tabs and line breaks,
+/// `offset` being its position in the input.
+fn check_encoding(input: &[u8], offset: usize) -> Result<(), Error> {
+    let mut bytes = input.iter().enumerate();
+    while let Some((i, &b)) = bytes.next() {
+        let message = match b {
+            b'%' => {
+                let escape = input.get(i + 1..i + 3);
+                if !escape.is_some_and(|e| e.iter().all(u8::is_ascii_hexdigit))
+                {
+                    let escape = &input[i..input.len().min(i + 3)];
+                    let escape = String::from_utf8_lossy(escape);
+                    format!("malformed percent-escape `{}`", escape)
+                } else {
+                    bytes.nth(1);
+                    // Line breaks and tabs are sent encoded by `textarea`s.
+                    match percent_decode(&input[i..i + 3]).next() {
+                        Some(b'\t' | b'\n' | b'\r') => continue,
+                        Some(b) if b.is_ascii_control() => format!(
+                            "percent-encoded control character 0x{:02X}",
+                            b
+                        ),
+                        _ => continue,
+                    }
+                }
+            }
+            0x80..=0xFF => format!("raw non-ASCII byte 0x{:02X}", b),
+            _ if b.is_ascii_control() => {
+                format!("control character 0x{:02X}", b)
+            }
+            _ => continue,
+        };
+        let message = format_args!("{} at byte {}", message, offset + i);
+        return Err(Error::new(ErrorKind::Malformed, message));
+    }
+    Ok(())
+}
+
+/// Decodes a pair as UTF-8, failing on invalid sequences instead of
+/// replacing them.
+fn parse_utf8_strict(
+    input: &[u8],
+    spaces: SpaceEncoding,
+) -> Option<Result<Pair<'static>, Error>> {
+    let (name, value) = split_pair(input)?;
+    let key = match String::from_utf8(unescape(name, spaces)) {
+        Ok(key) => key,
+        Err(err) => {
+            let key = String::from_utf8_lossy(err.as_bytes()).into_owned();
+            let err = Error::utf8_in("key", err.utf8_error()).at(&key, None);
+            return Some(Err(err));
+        }
+    };
+    let value = String::from_utf8(unescape(value, spaces)).map_err(|err| {
+        let value = String::from_utf8_lossy(err.as_bytes());
+        Error::utf8_in("value", err.utf8_error()).at(&key, Some(&value))
+    });
+    Some(value.map(|value| (Cow::Owned(key), Cow::Owned(value))))
+}
+
 struct PartIterator<'de> {
-    parse: UrlEncodedParse<'de>,
-    sequences: BTreeMap<Part<'de>, Vec<Part<'de>>>,
-    sequences_iter: btree_map::IntoIter<Part<'de>, Vec<Part<'de>>>,
+    input: Input<'de>,
+    decoding: Decoding,
+    options: Options,
+    duplicate_keys: DuplicateKeys,
+    limits: Limits,
+    /// The length of the input, if known and not checked yet.
+    input_len: Option<usize>,
+    pairs: usize,
+    /// The error that stopped the iteration, if any.
+    error: Option<Error>,
+    /// Where values record their parse errors when errors are collected.
+    errors: Option<Arc<Mutex<Vec<Error>>>>,
+    /// The pairs collected once a sequence or map is found, in the order
+    /// of the input, with an index by key to group the following pairs.
+    buffer: Map<Part<'de>>,
+    buffer_iter: vec::IntoIter<(Part<'de>, ValOrVec<Part<'de>>)>,
 }
 
 impl<'de> PartIterator<'de> {
-    fn new(parse: UrlEncodedParse<'de>) -> Self {
+    fn new(input: Input<'de>) -> Self {
         Self {
-            parse,
-            sequences: BTreeMap::new(),
-            sequences_iter: BTreeMap::new().into_iter(),
+            input,
+            decoding: Decoding::default(),
+            options: Options::default(),
+            duplicate_keys: DuplicateKeys::default(),
+            limits: Limits::default(),
+            input_len: None,
+            pairs: 0,
+            error: None,
+            errors: None,
+            buffer: Map::default(),
+            buffer_iter: vec![].into_iter(),
         }
     }
+
+    /// Returns the error that stopped the iteration if there is one, as it
+    /// takes precedence over whatever the visitor made of the truncated
+    /// input.
+    fn finish<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => result,
+        }
+    }
+
+    /// Collects a pair when duplicate keys are handled by a policy.
+    fn insert_unique(
+        &mut self,
+        k: Cow<'de, str>,
+        split: Option<SplitKey<'de>>,
+        value: Part<'de>,
+        span: Option<Range<usize>>,
+    ) -> Result<usize, Error> {
+        let options = self.options;
+        let (key, path) = split.unwrap_or_else(|| {
+            (Part::new(k.clone(), options, span.clone()), vec![])
+        });
+        let len = ValOrVec::insert_unique(
+            &mut self.buffer,
+            key,
+            path,
+            value,
+            self.duplicate_keys,
+            self.options.repeated_keys,
+        );
+        len.ok_or_else(|| {
+            let message = format_args!("duplicate key `{}`", k);
+            Error::new(ErrorKind::DuplicateKey, message)
+                .at(&k, None)
+                .at_span(span)
+        })
+    }
+
+    fn fail(&mut self, err: Error) -> Option<<Self as Iterator>::Item> {
+        self.error = Some(err);
+        self.buffer = Map::default();
+        self.buffer_iter = vec![].into_iter();
+        None
+    }
 }
 
 impl<'de> Iterator for PartIterator<'de> {
     type Item = (Part<'de>, ValOrVec<Part<'de>>);
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        }
+        if let Some(len) = self.input_len.take() {
+            let limit = self.limits.max_input_bytes;
+            if let Err(err) = check_limit("input bytes", len, limit) {
+                return self.fail(err);
+            }
+        }
+
         // Parse the input
-        while let Some((k, v)) = self.parse.next() {
-            if !k.ends_with("[]") {
-                return Some((Part(k), ValOrVec::Val(Part(v))));
+        while let Some(pair) = self.input.next(self.options, &mut self.decoding)
+        {
+            let (k, v, raw, span) = match pair {
+                Ok(pair) => pair,
+                Err(err) => return self.fail(err),
+            };
+            self.pairs += 1;
+            let limits = self.limits;
+            let checked = check_limit("pairs", self.pairs, limits.max_pairs)
+                .and_then(|()| {
+                    check_limit("key length", k.len(), limits.max_key_len)
+                })
+                .and_then(|()| {
+                    check_limit("value length", v.len(), limits.max_value_len)
+                });
+            if let Err(err) = checked {
+                return self.fail(err.at_span(span));
             }
 
-            self.sequences
-                .entry(Part(k))
-                .or_insert_with(|| Vec::with_capacity(4))
-                .push(Part(v));
+            let options = self.options;
+            let mut value = Part::new(v, options, span.clone());
+            value.raw = raw;
+            value.key = Some(k.clone());
+            if let Some(ref errors) = self.errors {
+                value.recorder = Some(Recorder {
+                    errors: errors.clone(),
+                });
+            }
+            let split = split_key(&k, options, &span);
+            if let Some((_, ref path)) = split {
+                // Nested keys are inserted and deserialized recursively.
+                let depth = path.len();
+                if let Err(err) =
+                    check_limit("depth", depth, Some(limits.max_depth))
+                {
+                    return self.fail(err.at(&k, None).at_span(span));
+                }
+            }
+            let len = if self.duplicate_keys != DuplicateKeys::KeepAll {
+                match self.insert_unique(k, split, value, span.clone()) {
+                    Ok(len) => len,
+                    Err(err) => return self.fail(err),
+                }
+            } else {
+                match split {
+                    Some((root, path)) => {
+                        ValOrVec::insert(&mut self.buffer, root, path, value)
+                    }
+                    None if self.options.repeated_keys => {
+                        let key = Part::new(k, options, span.clone());
+                        ValOrVec::append(&mut self.buffer, key, value)
+                    }
+                    None if self.buffer.is_empty() => {
+                        let key = Part::new(k, options, span.clone());
+                        return Some((key, ValOrVec::Val(value)));
+                    }
+                    None => {
+                        // A sequence or map is being collected, buffer the
+                        // pair to yield it in its original position.
+                        let key = Part::new(k, options, span.clone());
+                        self.buffer.push(key, ValOrVec::Val(value));
+                        continue;
+                    }
+                }
+            };
+            let limit = limits.max_sequence_len;
+            if let Err(err) = check_limit("sequence length", len, limit) {
+                return self.fail(err.at_span(span));
+            }
         }
 
-        if !self.sequences.is_empty() {
+        if !self.buffer.is_empty() {
             // Input just got exhausted, create an iterator over the collected
-            // sequences.
-            self.sequences_iter = mem::take(&mut self.sequences).into_iter();
+            // pairs, with each sequence and map at the position of its first
+            // key.
+            self.buffer_iter = mem::take(&mut self.buffer).into_iter();
         }
 
-        // Delegate to the iterator over the collected sequences
-        self.sequences_iter.next().map(|(mut k, v)| {
-            // Get rid of the trailing `[]`
-            match &mut k.0 {
-                Cow::Borrowed(s) => {
-                    k.0 = Cow::Borrowed(&s[..s.len() - 2]);
-                }
-                Cow::Owned(s) => {
-                    s.truncate(s.len() - 2);
-                }
+        // Delegate to the iterator over the collected pairs
+        self.buffer_iter.next()
+    }
+}
+
+/// The value of a top-level pair, which attaches the key and raw value of
+/// the pair to the errors raised while deserializing it.
+struct PairValue<'de> {
+    key: Cow<'de, str>,
+    value: ValOrVec<Part<'de>>,
+}
+
+impl<'de> PairValue<'de> {
+    fn new(
+        (key, value): (Part<'de>, ValOrVec<Part<'de>>),
+    ) -> (Part<'de>, Self) {
+        let pair_value = PairValue {
+            key: key.value.clone(),
+            value,
+        };
+        (key, pair_value)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for PairValue<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+macro_rules! forward_with_context {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method<V>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error>
+                where V: de::Visitor<'de>
+            {
+                let PairValue { key, value } = self;
+                let raw = match value {
+                    ValOrVec::Val(ref part) => Some(part.value.clone()),
+                    _ => None,
+                };
+                value
+                    .$method($($arg,)* visitor)
+                    .map_err(|e| e.at(&key, raw.as_deref()).in_field(&key))
             }
+        )*
+    }
+}
 
-            (k, ValOrVec::Vec(v))
-        })
+impl<'de> de::Deserializer<'de> for PairValue<'de> {
+    type Error = Error;
+
+    forward_with_context! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(
+            name: &'static str,
+            fields: &'static [&'static str]
+        ),
+        deserialize_enum(
+            name: &'static str,
+            variants: &'static [&'static str]
+        ),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+}
+
+/// The top-level pairs, read as the entries of a map or as the `(key, value)`
+/// elements of a sequence, see `GroupKey`.
+struct Pairs<'de, I> {
+    iter: I,
+    /// The remaining pairs of a group read with their original keys.
+    flat: vec::IntoIter<(Part<'de>, Part<'de>)>,
+    /// The value of the pair whose key was just read.
+    value: Option<PairValue<'de>>,
+}
+
+type Entry<'de> = (Part<'de>, ValOrVec<Part<'de>>);
+
+impl<'de, I> Pairs<'de, I>
+where
+    I: Iterator<Item = Entry<'de>>,
+{
+    fn new(iter: I) -> Self {
+        Pairs {
+            iter,
+            flat: vec![].into_iter(),
+            value: None,
+        }
+    }
+
+    fn next_entry(&mut self) -> Option<Entry<'de>> {
+        match self.flat.next() {
+            Some((key, value)) => Some((key, ValOrVec::Val(value))),
+            None => self.iter.next(),
+        }
+    }
+
+    /// Deserializes the key of an entry, keeping its value for `value`.
+    fn key<S>(
+        &mut self,
+        (key, mut value): Entry<'de>,
+        seed: S,
+    ) -> Result<S::Value, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let mut name = key.value.clone();
+        let key = seed.deserialize(GroupKey {
+            key,
+            value: &mut value,
+            name: &mut name,
+            flat: &mut self.flat,
+        })?;
+        self.value = Some(PairValue { key: name, value });
+        Ok(key)
+    }
+
+    fn value<S>(&mut self, seed: S) -> Result<S::Value, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let value = self.value.take().expect("a value is read before its key");
+        // Errors raised by the seed itself once the value is read, such as
+        // those of `#[serde(try_from)]`, are still located at the pair.
+        let key = value.key.clone();
+        let span = match value.value {
+            ValOrVec::Val(ref part) => part.span.clone(),
+            _ => None,
+        };
+        seed.deserialize(value)
+            .map_err(|e| at_pair(e, Some(&key), span))
+    }
+}
+
+impl<'de, I> de::MapAccess<'de> for Pairs<'de, I>
+where
+    I: Iterator<Item = Entry<'de>>,
+{
+    type Error = Error;
+
+    fn next_key_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        match self.next_entry() {
+            Some(entry) => self.key(entry, seed).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S>(&mut self, seed: S) -> Result<S::Value, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        self.value(seed)
+    }
+}
+
+impl<'de, I> de::SeqAccess<'de> for Pairs<'de, I>
+where
+    I: Iterator<Item = Entry<'de>>,
+{
+    type Error = Error;
+
+    fn next_element_seed<S>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        match self.next_entry() {
+            Some(entry) => seed
+                .deserialize(PairElement {
+                    pairs: self,
+                    entry: Some(entry),
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A top-level pair read as a `(key, value)` element of a sequence.
+struct PairElement<'a, 'de, I> {
+    pairs: &'a mut Pairs<'de, I>,
+    /// The pair, until its key is read.
+    entry: Option<Entry<'de>>,
+}
+
+impl<'de, I> de::SeqAccess<'de> for PairElement<'_, 'de, I>
+where
+    I: Iterator<Item = Entry<'de>>,
+{
+    type Error = Error;
+
+    fn next_element_seed<S>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        match self.entry.take() {
+            Some(entry) => self.pairs.key(entry, seed).map(Some),
+            None if self.pairs.value.is_some() => {
+                self.pairs.value(seed).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'de, I> de::Deserializer<'de> for PairElement<'_, 'de, I>
+where
+    I: Iterator<Item = Entry<'de>>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let pair = visitor.visit_seq(&mut self)?;
+        if self.entry.is_some() || self.pairs.value.take().is_some() {
+            return Err(de::Error::invalid_length(2, &"fewer elements"));
+        }
+        Ok(pair)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Forwards to the key part, see `GroupKey`.
+macro_rules! forward_to_key {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method<V>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error>
+                where V: de::Visitor<'de>
+            {
+                self.key.$method($($arg,)* visitor)
+            }
+        )*
+    }
+}
+
+/// The key of a top-level pair.
+///
+/// A group of bracketed pairs such as `filter[name]=x&filter[page]=2` is
+/// read as its root, `filter`, when the key is a field of a struct or any
+/// other identifier, but as the pairs themselves with their original keys
+/// when the key is a string, so that flat maps and sequences of pairs of
+/// strings get the keys as they are in the input.
+struct GroupKey<'a, 'de> {
+    key: Part<'de>,
+    value: &'a mut ValOrVec<Part<'de>>,
+    /// The key used in the errors of the value.
+    name: &'a mut Cow<'de, str>,
+    /// Where the remaining pairs of a group read flat go.
+    flat: &'a mut vec::IntoIter<(Part<'de>, Part<'de>)>,
+}
+
+impl<'de> GroupKey<'_, 'de> {
+    /// Reads a group flat, returning the key of its first pair and leaving
+    /// the other pairs to be read next.
+    fn flatten(self) -> Part<'de> {
+        if !matches!(*self.value, ValOrVec::Map(_)) {
+            return self.key;
+        }
+        let mut values = vec![];
+        let group = mem::replace(self.value, ValOrVec::Vec(vec![]));
+        collect_values(group, &mut values);
+        values.sort_by_key(|value| value.span.as_ref().map(|span| span.start));
+
+        let root = self.key.value;
+        let options = self.key.options;
+        let mut pairs = values.into_iter().map(|value| {
+            let key = value.key.clone().unwrap_or_else(|| root.clone());
+            (Part::new(key, options, value.span.clone()), value)
+        });
+        let (key, value) = pairs.next().expect("a group has a value");
+        *self.flat = pairs.collect::<Vec<_>>().into_iter();
+        *self.value = ValOrVec::Val(value);
+        *self.name = key.value.clone();
+        key
+    }
+}
+
+/// Collects the values of a group in the order they are grouped in.
+fn collect_values<'de>(node: ValOrVec<Part<'de>>, values: &mut Vec<Part<'de>>) {
+    match node {
+        ValOrVec::Val(value) => values.push(value),
+        ValOrVec::Vec(vec) => values.extend(vec),
+        ValOrVec::Map(map) => {
+            for (_, node) in map {
+                collect_values(node, values);
+            }
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for GroupKey<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.flatten().deserialize_str(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.flatten().deserialize_string(visitor)
+    }
+
+    forward_to_key! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(
+            name: &'static str,
+            fields: &'static [&'static str]
+        ),
+        deserialize_enum(
+            name: &'static str,
+            variants: &'static [&'static str]
+        ),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+}
+
+/// A key split into its root and the path of segments between brackets.
+type SplitKey<'de> = (Part<'de>, Vec<Segment<Part<'de>>>);
+
+/// Splits a key such as `user[address][city]` or `tags[]` into its root and
+/// the path of segments between brackets.
+///
+/// Returns `None` if the key has no root or no brackets, or if the brackets
+/// are not well-formed, in which case the key is used as is.
+fn split_key<'de>(
+    key: &Cow<'de, str>,
+    options: Options,
+    span: &Option<Range<usize>>,
+) -> Option<SplitKey<'de>> {
+    let root_len = key.find('[')?;
+    if root_len == 0 {
+        return None;
+    }
+    let mut path = vec![];
+    let mut rest = &key[root_len..];
+    let mut start = root_len;
+
+    while !rest.is_empty() {
+        if !rest.starts_with('[') {
+            return None;
+        }
+        let len = rest[1..].find(&['[', ']'][..])?;
+        if rest.as_bytes()[len + 1] != b']' {
+            return None;
+        }
+        if len == 0 {
+            // Only a trailing `[]` is supported.
+            if rest.len() != 2 {
+                return None;
+            }
+            path.push(Segment::Push);
+        } else {
+            let range = start + 1..start + 1 + len;
+            let value = slice_key(key, range);
+            let mut segment = Part::new(value, options, span.clone());
+            segment.key = Some(key.clone());
+            path.push(Segment::Key(segment));
+        }
+        rest = &rest[len + 2..];
+        start += len + 2;
+    }
+
+    let root = Part::new(slice_key(key, 0..root_len), options, span.clone());
+    Some((root, path))
+}
+
+fn slice_key<'de>(key: &Cow<'de, str>, range: Range<usize>) -> Cow<'de, str> {
+    match *key {
+        Cow::Borrowed(key) => Cow::Borrowed(&key[range]),
+        Cow::Owned(ref key) => Cow::Owned(key[range].to_owned()),
+    }
+}
+
+#[derive(Clone)]
+struct Part<'de> {
+    value: Cow<'de, str>,
+    /// The value as it is in the input, still percent-encoded, if known.
+    raw: Option<&'de [u8]>,
+    options: Options,
+    /// The full key of the pair this part comes from, such as `user[age]`,
+    /// if it is not the part itself.
+    key: Option<Cow<'de, str>>,
+    /// The byte range in the input of the pair this part comes from.
+    span: Option<Range<usize>>,
+    recorder: Option<Recorder>,
+}
+
+/// Records the parse errors of a value when errors are collected, see
+/// `Deserializer::deserialize_collecting_errors`.
+#[derive(Clone)]
+struct Recorder {
+    errors: Arc<Mutex<Vec<Error>>>,
+}
+
+impl<'de> Part<'de> {
+    fn new(
+        value: Cow<'de, str>,
+        options: Options,
+        span: Option<Range<usize>>,
+    ) -> Self {
+        Part {
+            value,
+            raw: None,
+            options,
+            key: None,
+            span,
+            recorder: None,
+        }
+    }
+}
+
+/// Sets the key and byte range of the pair a part comes from on an error
+/// raised while deserializing the part.
+fn at_pair(err: Error, key: Option<&str>, span: Option<Range<usize>>) -> Error {
+    let err = match key {
+        Some(key) => err.at(key, None),
+        None => err,
+    };
+    err.at_span(span)
+}
+
+impl fmt::Display for Part<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+// Parts are compared to group keys, wherever they are in the input.
+impl PartialEq for Part<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for Part<'_> {}
+
+impl Grouped for Part<'_> {
+    fn sequence_gaps(&self) -> SequenceGaps {
+        self.options.sequence_gaps
+    }
+
+    fn pair_key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// Only canonical indices are indices, so that `a[01]` is a map key
+    /// rather than a second `a[1]`.
+    fn index(&self) -> Option<usize> {
+        let value = &*self.value;
+        let canonical = value == "0" || !value.starts_with('0');
+        if canonical && parse_index(value).is_some() {
+            value.parse().ok()
+        } else {
+            None
+        }
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-struct Part<'de>(Cow<'de, str>);
+impl Hash for Part<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
 
-impl<'de> IntoDeserializer<'de> for Part<'de> {
+impl<'de> IntoDeserializer<'de, Error> for Part<'de> {
     type Deserializer = Self;
 
     fn into_deserializer(self) -> Self::Deserializer {
@@ -226,20 +1969,58 @@ impl<'de> IntoDeserializer<'de> for Part<'de> {
 }
 
 macro_rules! forward_parsed_value {
-    ($($ty:ident => $method:ident,)*) => {
+    ($($ty:ident => $method:ident($placeholder:expr),)*) => {
         $(
             fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
                 where V: de::Visitor<'de>
             {
-                match self.0.parse::<$ty>() {
-                    Ok(val) => val.into_deserializer().$method(visitor),
-                    Err(e) => Err(de::Error::custom(e))
+                let Part { value, key, span, recorder, .. } = self;
+                let err = match value.parse::<$ty>() {
+                    Ok(val) => {
+                        return val
+                            .into_deserializer()
+                            .$method(visitor)
+                            .map_err(|e: Error| at_pair(e, key.as_deref(), span));
+                    }
+                    Err(e) => {
+                        let err = Error::new(ErrorKind::Parse, e).at_value(&value);
+                        at_pair(err, key.as_deref(), span.clone())
+                    }
+                };
+                match recorder {
+                    Some(recorder) => {
+                        recorder.errors.lock().unwrap().push(err);
+                        let placeholder: $ty = $placeholder;
+                        placeholder
+                            .into_deserializer()
+                            .$method(visitor)
+                            .map_err(|e: Error| at_pair(e, key.as_deref(), span))
+                    }
+                    None => Err(err),
                 }
             }
         )*
     }
 }
 
+/// Forwards to `deserialize_str` the types whose values are never inferred,
+/// see `Deserializer::infer_types`.
+macro_rules! forward_to_deserialize_str {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method<V>(
+                self,
+                $(_: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error>
+                where V: de::Visitor<'de>
+            {
+                self.deserialize_str(visitor)
+            }
+        )*
+    }
+}
+
 impl<'de> de::Deserializer<'de> for Part<'de> {
     type Error = Error;
 
@@ -247,10 +2028,47 @@ impl<'de> de::Deserializer<'de> for Part<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.0 {
+        if !self.options.infer_types {
+            return self.deserialize_str(visitor);
+        }
+        // Only values which would be formatted back as they are, so that
+        // `007` or `1.50` stay strings.
+        let value = &*self.value;
+        let canonical = |formatted: &str| formatted == value;
+        let result = if let Ok(v) = value.parse::<bool>() {
+            visitor.visit_bool(v)
+        } else if let Some(v) = value
+            .parse::<u64>()
+            .ok()
+            .filter(|v| canonical(&v.to_string()))
+        {
+            visitor.visit_u64(v)
+        } else if let Some(v) = value
+            .parse::<i64>()
+            .ok()
+            .filter(|v| canonical(&v.to_string()))
+        {
+            visitor.visit_i64(v)
+        } else if let Some(v) = value.parse::<f64>().ok().filter(|v| {
+            v.is_finite() && canonical(ryu::Buffer::new().format_finite(*v))
+        }) {
+            visitor.visit_f64(v)
+        } else {
+            return self.deserialize_str(visitor);
+        };
+        result.map_err(|e: Error| at_pair(e, self.key.as_deref(), self.span))
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let (key, span) = (self.key, self.span);
+        let result = match self.value {
             Cow::Borrowed(value) => visitor.visit_borrowed_str(value),
             Cow::Owned(value) => visitor.visit_string(value),
-        }
+        };
+        result.map_err(|e: Error| at_pair(e, key.as_deref(), span))
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -260,16 +2078,63 @@ impl<'de> de::Deserializer<'de> for Part<'de> {
         visitor.visit_some(self)
     }
 
+    /// Percent-decodes the raw value of the pair if it is known, without
+    /// decoding the bytes as text, so that binary values are left intact.
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let (key, span) = (self.key, self.span);
+        let spaces = self.options.spaces;
+        let plus = spaces == SpaceEncoding::Plus;
+        let result = match self.raw {
+            Some(raw)
+                if !raw.iter().any(|&b| b == b'%' || plus && b == b'+') =>
+            {
+                visitor.visit_borrowed_bytes(raw)
+            }
+            Some(raw) => visitor.visit_byte_buf(unescape(raw, spaces)),
+            None => match self.value {
+                Cow::Borrowed(value) => {
+                    visitor.visit_borrowed_bytes(value.as_bytes())
+                }
+                Cow::Owned(value) => visitor.visit_byte_buf(value.into_bytes()),
+            },
+        };
+        result.map_err(|e: Error| at_pair(e, key.as_deref(), span))
+    }
+
+    fn deserialize_byte_buf<V>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
     fn deserialize_enum<V>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_enum(ValueEnumAccess(self.0))
+        let (key, span) = (self.key, self.span);
+        let matching = self.options.variant_matching;
+        let variant = matching.resolve(self.value, variants);
+        let index =
+            if self.options.variant_indices && !variants.contains(&&*variant) {
+                parse_index(&variant)
+            } else {
+                None
+            };
+        visitor
+            .visit_enum(ValueEnumAccess { variant, index })
+            .map_err(|e| at_pair(e, key.as_deref(), span))
     }
 
     fn deserialize_newtype_struct<V>(
@@ -283,39 +2148,95 @@ impl<'de> de::Deserializer<'de> for Part<'de> {
         visitor.visit_newtype_struct(self)
     }
 
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if let Some(delimiter) = self.options.list_delimiter {
+            let options = self.options;
+            let pieces: Vec<Cow<'de, str>> = match self.value {
+                Cow::Borrowed("") => vec![],
+                Cow::Borrowed(value) => {
+                    value.split(delimiter).map(Cow::Borrowed).collect()
+                }
+                Cow::Owned(ref value) if value.is_empty() => vec![],
+                Cow::Owned(value) => value
+                    .split(delimiter)
+                    .map(|piece| Cow::Owned(piece.to_owned()))
+                    .collect(),
+            };
+            let (key, span) = (self.key, self.span);
+            let limit = options.max_sequence_len;
+            if let Err(err) =
+                check_limit("sequence length", pieces.len(), limit)
+            {
+                return Err(at_pair(err, key.as_deref(), span));
+            }
+            let recorder = self.recorder;
+            let parts = pieces.into_iter().map(|piece| Part {
+                key: key.clone(),
+                recorder: recorder.clone(),
+                ..Part::new(piece, options, span.clone())
+            });
+            visitor.visit_seq(Elements::new(parts.enumerate()))
+        } else if self.options.repeated_keys {
+            visitor.visit_seq(Elements::new(iter::once((0, self))))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    forward_to_deserialize_str! {
+        deserialize_char(),
+        deserialize_string(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_struct(
+            name: &'static str,
+            fields: &'static [&'static str]
+        ),
+        deserialize_identifier(),
+        deserialize_tuple(len: usize),
+        deserialize_map(),
+    }
+
     forward_to_deserialize_any! {
-        char
-        str
-        string
-        unit
-        bytes
-        byte_buf
-        unit_struct
-        tuple_struct
-        struct
-        identifier
-        tuple
         ignored_any
-        seq
-        map
     }
 
+    // The placeholders of collected errors, see
+    // `Deserializer::deserialize_collecting_errors`.
     forward_parsed_value! {
-        bool => deserialize_bool,
-        u8 => deserialize_u8,
-        u16 => deserialize_u16,
-        u32 => deserialize_u32,
-        u64 => deserialize_u64,
-        i8 => deserialize_i8,
-        i16 => deserialize_i16,
-        i32 => deserialize_i32,
-        i64 => deserialize_i64,
-        f32 => deserialize_f32,
-        f64 => deserialize_f64,
+        bool => deserialize_bool(false),
+        u8 => deserialize_u8(1),
+        u16 => deserialize_u16(1),
+        u32 => deserialize_u32(1),
+        u64 => deserialize_u64(1),
+        i8 => deserialize_i8(1),
+        i16 => deserialize_i16(1),
+        i32 => deserialize_i32(1),
+        i64 => deserialize_i64(1),
+        f32 => deserialize_f32(1.0),
+        f64 => deserialize_f64(1.0),
     }
 }
 
-struct ValueEnumAccess<'de>(Cow<'de, str>);
+struct ValueEnumAccess<'de> {
+    variant: Cow<'de, str>,
+    /// The index of the variant, if it is given as a number, see
+    /// `Deserializer::variant_indices`.
+    index: Option<u64>,
+}
+
+/// Parses a variant index, made of ASCII digits only.
+fn parse_index(value: &str) -> Option<u64> {
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        value.parse().ok()
+    } else {
+        None
+    }
+}
 
 impl<'de> de::EnumAccess<'de> for ValueEnumAccess<'de> {
     type Error = Error;
@@ -328,7 +2249,12 @@ impl<'de> de::EnumAccess<'de> for ValueEnumAccess<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let variant = seed.deserialize(self.0.into_deserializer())?;
+        let variant = match self.index {
+            Some(index) => seed.deserialize(
+                IntoDeserializer::<Error>::into_deserializer(index),
+            )?,
+            None => seed.deserialize(self.variant.into_deserializer())?,
+        };
         Ok((variant, UnitOnlyVariantAccess))
     }
 }
@@ -346,7 +2272,7 @@ impl<'de> de::VariantAccess<'de> for UnitOnlyVariantAccess {
     where
         T: de::DeserializeSeed<'de>,
     {
-        Err(Error::custom("expected unit variant"))
+        Err(Error::new(ErrorKind::Unsupported, "expected unit variant"))
     }
 
     fn tuple_variant<V>(
@@ -357,7 +2283,7 @@ impl<'de> de::VariantAccess<'de> for UnitOnlyVariantAccess {
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::custom("expected unit variant"))
+        Err(Error::new(ErrorKind::Unsupported, "expected unit variant"))
     }
 
     fn struct_variant<V>(
@@ -368,6 +2294,6 @@ impl<'de> de::VariantAccess<'de> for UnitOnlyVariantAccess {
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::custom("expected unit variant"))
+        Err(Error::new(ErrorKind::Unsupported, "expected unit variant"))
     }
 }
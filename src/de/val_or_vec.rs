@@ -1,30 +1,330 @@
-use serde::de::{
-    self,
-    value::{Error, SeqDeserializer},
-    Deserializer, IntoDeserializer,
-};
+use serde::de::{self, Deserializer, IntoDeserializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::FromIterator;
+use std::mem;
+use std::vec;
+
+use super::{DuplicateKeys, Error, ErrorKind, SequenceGaps};
 
 pub enum ValOrVec<T> {
     Val(T),
     Vec(Vec<T>),
+    Map(Map<T>),
+}
+
+/// The entries of a map, in the order of their first key, along with the
+/// positions of the entries of each key so that a pair can be added to its
+/// sequence or map without scanning the previous entries.
+pub struct Map<T> {
+    entries: Vec<(T, ValOrVec<T>)>,
+    positions: HashMap<T, Positions>,
+}
+
+/// The positions of the entries of a key in a `Map`.
+#[derive(Clone, Copy)]
+struct Positions {
+    /// The first entry.
+    first: usize,
+    /// The last map.
+    map: Option<usize>,
+    /// The last sequence.
+    vec: Option<usize>,
+    /// The last scalar value or sequence.
+    val_or_vec: Option<usize>,
+}
+
+impl Positions {
+    fn record<T>(&mut self, index: usize, node: &ValOrVec<T>) {
+        match node {
+            ValOrVec::Val(_) => self.val_or_vec = Some(index),
+            ValOrVec::Vec(_) => {
+                self.vec = Some(index);
+                self.val_or_vec = Some(index);
+            }
+            ValOrVec::Map(_) => self.map = Some(index),
+        }
+    }
+}
+
+impl<T> Map<T> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T: Clone + Eq + Hash> Map<T> {
+    /// Adds an entry at the end of the map and returns its position.
+    pub fn push(&mut self, key: T, node: ValOrVec<T>) -> usize {
+        let index = self.entries.len();
+        match self.positions.get_mut(&key) {
+            Some(positions) => positions.record(index, &node),
+            None => {
+                let mut positions = Positions {
+                    first: index,
+                    map: None,
+                    vec: None,
+                    val_or_vec: None,
+                };
+                positions.record(index, &node);
+                self.positions.insert(key.clone(), positions);
+            }
+        }
+        self.entries.push((key, node));
+        index
+    }
+
+    /// Updates the positions of the key of the entry at `index` after its
+    /// node changed.
+    fn record(&mut self, index: usize) {
+        let (key, node) = &self.entries[index];
+        if let Some(positions) = self.positions.get_mut(key) {
+            positions.record(index, node);
+        }
+    }
+
+    fn positions(&self, key: &T) -> Option<Positions> {
+        self.positions.get(key).copied()
+    }
+}
+
+impl<T> Default for Map<T> {
+    fn default() -> Self {
+        Map {
+            entries: vec![],
+            positions: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash> FromIterator<(T, ValOrVec<T>)> for Map<T> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (T, ValOrVec<T>)>,
+    {
+        let mut map = Map::default();
+        for (key, node) in iter {
+            map.push(key, node);
+        }
+        map
+    }
+}
+
+impl<T> IntoIterator for Map<T> {
+    type Item = (T, ValOrVec<T>);
+    type IntoIter = vec::IntoIter<(T, ValOrVec<T>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// A key or value grouped in a `ValOrVec`, which knows the options of its
+/// deserializer.
+pub trait Grouped {
+    fn sequence_gaps(&self) -> SequenceGaps;
+
+    /// Returns the full key of the pair this part comes from, if known.
+    fn pair_key(&self) -> Option<&str>;
+
+    /// Parses this key as the index of an entry such as `items[2]`.
+    fn index(&self) -> Option<usize>;
+}
+
+/// A segment of a bracketed key path such as `[address]` or `[]`.
+pub enum Segment<T> {
+    Key(T),
+    Push,
+}
+
+impl<T: Clone + Eq + Hash> ValOrVec<T> {
+    /// Inserts `value` at `path` below `key` in the given map.
+    ///
+    /// Existing sequences and maps are extended, but a scalar value always
+    /// gets its own entry, so duplicate keys are left for the visitor to
+    /// handle just like at the top level.
+    ///
+    /// Returns the length of the sequence or map `value` was added to.
+    pub fn insert(
+        map: &mut Map<T>,
+        key: T,
+        path: Vec<Segment<T>>,
+        value: T,
+    ) -> usize {
+        let mut path = path.into_iter();
+        let segment = match path.next() {
+            Some(segment) => segment,
+            None => {
+                map.push(key, ValOrVec::Val(value));
+                return map.len();
+            }
+        };
+        let index = map.positions(&key).and_then(|positions| match segment {
+            Segment::Key(_) => positions.map,
+            Segment::Push => positions.vec,
+        });
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let node = match segment {
+                    Segment::Key(_) => ValOrVec::Map(Map::default()),
+                    Segment::Push => ValOrVec::Vec(vec![]),
+                };
+                map.push(key, node)
+            }
+        };
+        match (&mut map.entries[index].1, segment) {
+            (ValOrVec::Map(map), Segment::Key(key)) => {
+                Self::insert(map, key, path.collect(), value)
+            }
+            (ValOrVec::Vec(vec), Segment::Push) => {
+                vec.push(value);
+                vec.len()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Appends `value` to the entry for `key`, turning it into a sequence if
+    /// that key was already seen.
+    ///
+    /// Returns the number of values for `key`.
+    pub fn append(map: &mut Map<T>, key: T, value: T) -> usize {
+        let index = map
+            .positions(&key)
+            .and_then(|positions| positions.val_or_vec);
+        let index = match index {
+            Some(index) => index,
+            None => {
+                map.push(key, ValOrVec::Val(value));
+                return 1;
+            }
+        };
+        let node = &mut map.entries[index].1;
+        match node {
+            ValOrVec::Vec(vec) => {
+                vec.push(value);
+                vec.len()
+            }
+            _ => {
+                let vec = ValOrVec::Vec(Vec::with_capacity(2));
+                if let ValOrVec::Val(first) = mem::replace(node, vec) {
+                    *node = ValOrVec::Vec(vec![first, value]);
+                }
+                map.record(index);
+                2
+            }
+        }
+    }
+
+    /// Inserts `value` at `path` below `key` in map entries where each key
+    /// appears at most once, resolving conflicts with `policy`.
+    ///
+    /// Sequences and maps are extended, but a scalar value conflicts with
+    /// any other value for the same key, and so do a sequence and a map.
+    /// With `repeated`, scalar values and `[]` elements for the same key are
+    /// all collected into one sequence instead.
+    ///
+    /// Returns the length of the sequence or map `value` was added to, or
+    /// `None` if `value` conflicts with an existing value and `policy` is
+    /// `DuplicateKeys::Reject`.
+    pub fn insert_unique(
+        map: &mut Map<T>,
+        key: T,
+        path: Vec<Segment<T>>,
+        value: T,
+        policy: DuplicateKeys,
+        repeated: bool,
+    ) -> Option<usize> {
+        let mut path = path.into_iter();
+        let segment = path.next();
+        let index = map.positions(&key).map(|positions| positions.first);
+        let (index, fresh) = match index {
+            Some(index) if map.entries[index].1.fits(&segment, repeated) => {
+                (index, false)
+            }
+            Some(index) => match policy {
+                DuplicateKeys::Reject => return None,
+                DuplicateKeys::First => return Some(0),
+                DuplicateKeys::KeepAll | DuplicateKeys::Last => (index, true),
+            },
+            None => (map.push(key, ValOrVec::Vec(vec![])), true),
+        };
+        if fresh {
+            let node = match segment {
+                None => {
+                    map.entries[index].1 = ValOrVec::Val(value);
+                    map.record(index);
+                    return Some(1);
+                }
+                Some(Segment::Key(_)) => ValOrVec::Map(Map::default()),
+                Some(Segment::Push) => ValOrVec::Vec(vec![]),
+            };
+            map.entries[index].1 = node;
+            map.record(index);
+        }
+        match (&mut map.entries[index].1, segment) {
+            (ValOrVec::Map(map), Some(Segment::Key(key))) => {
+                Self::insert_unique(
+                    map,
+                    key,
+                    path.collect(),
+                    value,
+                    policy,
+                    false,
+                )
+            }
+            (ValOrVec::Vec(vec), _) => {
+                vec.push(value);
+                Some(vec.len())
+            }
+            (node, _) => {
+                // A scalar value for a repeated key.
+                let vec = ValOrVec::Vec(Vec::with_capacity(2));
+                if let ValOrVec::Val(first) = mem::replace(node, vec) {
+                    *node = ValOrVec::Vec(vec![first, value]);
+                }
+                Some(2)
+            }
+        }
+    }
+
+    fn fits(&self, segment: &Option<Segment<T>>, repeated: bool) -> bool {
+        match (self, segment) {
+            (ValOrVec::Map(_), Some(Segment::Key(_)))
+            | (ValOrVec::Vec(_), Some(Segment::Push)) => true,
+            (ValOrVec::Val(_), None)
+            | (ValOrVec::Val(_), Some(Segment::Push))
+            | (ValOrVec::Vec(_), None) => repeated,
+            _ => false,
+        }
+    }
 }
 
 impl<T> ValOrVec<T> {
-    fn deserialize_val<U, E, F>(self, f: F) -> Result<U, E>
+    fn deserialize_val<U, F>(self, f: F) -> Result<U, Error>
     where
-        F: FnOnce(T) -> Result<U, E>,
-        E: de::Error,
+        F: FnOnce(T) -> Result<U, Error>,
     {
         match self {
             ValOrVec::Val(val) => f(val),
-            ValOrVec::Vec(_) => Err(de::Error::custom("unsupported value")),
+            ValOrVec::Vec(_) | ValOrVec::Map(_) => Err(Error::unsupported()),
         }
     }
 }
 
-impl<'de, T> IntoDeserializer<'de> for ValOrVec<T>
+impl<'de, T> IntoDeserializer<'de, Error> for ValOrVec<T>
 where
-    T: IntoDeserializer<'de> + Deserializer<'de, Error = Error>,
+    T: IntoDeserializer<'de, Error>
+        + Deserializer<'de, Error = Error>
+        + Clone
+        + fmt::Display
+        + Grouped,
 {
     type Deserializer = Self;
 
@@ -47,7 +347,11 @@ macro_rules! forward_to_part {
 
 impl<'de, T> Deserializer<'de> for ValOrVec<T>
 where
-    T: IntoDeserializer<'de> + Deserializer<'de, Error = Error>,
+    T: IntoDeserializer<'de, Error>
+        + Deserializer<'de, Error = Error>
+        + Clone
+        + fmt::Display
+        + Grouped,
 {
     type Error = Error;
 
@@ -58,6 +362,7 @@ where
         match self {
             ValOrVec::Val(val) => val.deserialize_any(visitor),
             ValOrVec::Vec(_) => self.deserialize_seq(visitor),
+            ValOrVec::Map(_) => self.deserialize_map(visitor),
         }
     }
 
@@ -68,11 +373,77 @@ where
         match self {
             ValOrVec::Val(val) => val.deserialize_seq(visitor),
             ValOrVec::Vec(vec) => {
-                visitor.visit_seq(SeqDeserializer::new(vec.into_iter()))
+                visitor.visit_seq(Elements::new(vec.into_iter().enumerate()))
+            }
+            ValOrVec::Map(entries) => {
+                let mut gaps = SequenceGaps::default();
+                let mut indexed = entries
+                    .into_iter()
+                    .map(|(key, node)| {
+                        gaps = key.sequence_gaps();
+                        let index =
+                            key.index().ok_or_else(Error::unsupported)?;
+                        Ok((index, key, node))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                indexed.sort_by_key(|&(index, _, _)| index);
+                if let Some(pair) =
+                    indexed.windows(2).find(|pair| pair[0].0 == pair[1].0)
+                {
+                    let message = format_args!("duplicate index {}", pair[0].0);
+                    let err = Error::new(ErrorKind::DuplicateKey, message);
+                    return Err(match pair[1].1.pair_key() {
+                        Some(key) => err.at(key, None),
+                        None => err,
+                    });
+                }
+                if gaps == SequenceGaps::Reject {
+                    let missing = indexed
+                        .iter()
+                        .enumerate()
+                        .find(|&(expected, &(index, _, _))| index != expected);
+                    if let Some((missing, _)) = missing {
+                        let message = format_args!("missing index {}", missing);
+                        return Err(Error::new(ErrorKind::Malformed, message));
+                    }
+                }
+                let elements =
+                    indexed.into_iter().map(|(i, _, node)| (i, node));
+                visitor.visit_seq(Elements::new(elements))
+            }
+        }
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            ValOrVec::Val(val) => val.deserialize_map(visitor),
+            ValOrVec::Vec(_) => Err(Error::unsupported()),
+            ValOrVec::Map(entries) => {
+                let mut map = Entries {
+                    iter: entries.into_iter(),
+                    current: None,
+                    count: 0,
+                };
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
             }
         }
     }
 
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            ValOrVec::Val(val) => val.deserialize_option(visitor),
+            ValOrVec::Vec(_) | ValOrVec::Map(_) => visitor.visit_some(self),
+        }
+    }
+
     fn deserialize_enum<V>(
         self,
         name: &'static str,
@@ -82,9 +453,23 @@ where
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_val(move |val| {
-            val.deserialize_enum(name, variants, visitor)
-        })
+        match self {
+            ValOrVec::Map(map) => {
+                let mut entries = map.into_iter();
+                match (entries.next(), entries.next()) {
+                    (Some((key, value)), None) => {
+                        visitor.visit_enum(Variant { key, value })
+                    }
+                    _ => Err(Error::new(
+                        ErrorKind::Unsupported,
+                        "expected a single enum variant",
+                    )),
+                }
+            }
+            _ => self.deserialize_val(move |val| {
+                val.deserialize_enum(name, variants, visitor)
+            }),
+        }
     }
 
     fn deserialize_tuple<V>(
@@ -95,7 +480,12 @@ where
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_val(move |val| val.deserialize_tuple(len, visitor))
+        match self {
+            ValOrVec::Val(val) => val.deserialize_tuple(len, visitor),
+            ValOrVec::Vec(_) | ValOrVec::Map(_) => {
+                self.deserialize_seq(visitor)
+            }
+        }
     }
 
     fn deserialize_struct<V>(
@@ -107,9 +497,12 @@ where
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_val(move |val| {
-            val.deserialize_struct(name, fields, visitor)
-        })
+        match self {
+            ValOrVec::Map(_) => self.deserialize_map(visitor),
+            _ => self.deserialize_val(move |val| {
+                val.deserialize_struct(name, fields, visitor)
+            }),
+        }
     }
 
     fn deserialize_unit_struct<V>(
@@ -147,9 +540,12 @@ where
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_val(move |val| {
-            val.deserialize_newtype_struct(name, visitor)
-        })
+        match self {
+            ValOrVec::Val(val) => val.deserialize_newtype_struct(name, visitor),
+            ValOrVec::Vec(_) | ValOrVec::Map(_) => {
+                visitor.visit_newtype_struct(self)
+            }
+        }
     }
 
     fn deserialize_ignored_any<V>(
@@ -180,8 +576,209 @@ where
         deserialize_i64,
         deserialize_f32,
         deserialize_f64,
-        deserialize_option,
         deserialize_identifier,
-        deserialize_map,
+    }
+}
+
+/// Gives access to the elements of a sequence, adding their index to the
+/// path of errors.
+pub struct Elements<I> {
+    iter: I,
+}
+
+impl<I> Elements<I> {
+    pub fn new(iter: I) -> Self {
+        Elements { iter }
+    }
+}
+
+impl<'de, I, T> de::SeqAccess<'de> for Elements<I>
+where
+    I: Iterator<Item = (usize, T)>,
+    T: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_element_seed<S>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((index, value)) => seed
+                .deserialize(value.into_deserializer())
+                .map(Some)
+                .map_err(|e| e.in_index(index)),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+/// Gives access to the entries of a nested map, adding their key to the
+/// path of errors.
+struct Entries<T> {
+    iter: vec::IntoIter<(T, ValOrVec<T>)>,
+    /// The key and value of the entry being deserialized.
+    current: Option<(T, ValOrVec<T>)>,
+    count: usize,
+}
+
+impl<T> Entries<T> {
+    fn end(self) -> Result<(), Error> {
+        let remaining = self.iter.len();
+        if remaining == 0 {
+            Ok(())
+        } else {
+            Err(de::Error::invalid_length(
+                self.count + remaining,
+                &ExpectedInMap(self.count),
+            ))
+        }
+    }
+}
+
+impl<'de, T> de::MapAccess<'de> for Entries<T>
+where
+    T: IntoDeserializer<'de, Error>
+        + Deserializer<'de, Error = Error>
+        + Clone
+        + fmt::Display
+        + Grouped,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.count += 1;
+                let result = seed
+                    .deserialize(key.clone())
+                    .map(Some)
+                    .map_err(|e| e.in_field(&key.to_string()));
+                self.current = Some((key, value));
+                result
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let (key, value) = self
+            .current
+            .take()
+            .expect("MapAccess::next_value called before next_key");
+        seed.deserialize(value)
+            .map_err(|e| e.in_field(&key.to_string()))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// Gives access to a newtype, tuple or struct variant written as a single
+/// bracketed key, such as `shape[circle]=3`.
+struct Variant<T> {
+    key: T,
+    value: ValOrVec<T>,
+}
+
+impl<'de, T> de::EnumAccess<'de> for Variant<T>
+where
+    T: IntoDeserializer<'de, Error>
+        + Deserializer<'de, Error = Error>
+        + Clone
+        + fmt::Display
+        + Grouped,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, Self), Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let variant = seed
+            .deserialize(self.key.clone())
+            .map_err(|e| e.in_field(&self.key.to_string()))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, T> de::VariantAccess<'de> for Variant<T>
+where
+    T: IntoDeserializer<'de, Error>
+        + Deserializer<'de, Error = Error>
+        + Clone
+        + fmt::Display
+        + Grouped,
+{
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(de::Error::invalid_type(
+            de::Unexpected::NewtypeVariant,
+            &"unit variant",
+        ))
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let key = self.key;
+        seed.deserialize(self.value)
+            .map_err(|e| e.in_field(&key.to_string()))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let key = self.key;
+        self.value
+            .deserialize_tuple(len, visitor)
+            .map_err(|e| e.in_field(&key.to_string()))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let key = self.key;
+        self.value
+            .deserialize_map(visitor)
+            .map_err(|e| e.in_field(&key.to_string()))
+    }
+}
+
+struct ExpectedInMap(usize);
+
+impl de::Expected for ExpectedInMap {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 1 {
+            write!(formatter, "1 element in map")
+        } else {
+            write!(formatter, "{} elements in map", self.0)
+        }
     }
 }
@@ -0,0 +1,242 @@
+//! How keys and values are encoded, in charsets other than UTF-8 and with
+//! spaces encoded as `+` or `%20`.
+
+use encoding_rs::{Encoding, X_USER_DEFINED};
+use form_urlencoded::EncodingOverride;
+use form_urlencoded::Serializer as UrlEncodedSerializer;
+use form_urlencoded::Target as UrlEncodedTarget;
+use percent_encoding::percent_decode;
+use std::borrow::Cow;
+
+/// The character encoding in which keys and values are percent-encoded.
+///
+/// Charsets are named by the labels of the [Encoding Standard], as web
+/// browsers do: `latin1` and `ISO-8859-1` are labels of `windows-1252`,
+/// and `sjis` is a label of `Shift_JIS`. Keys and values can only contain
+/// the characters of their charset, other characters are serialized as
+/// HTML numeric character references such as `&#8364;`.
+///
+/// ```
+/// use serde_urlencoded::Charset;
+///
+/// let charset = Charset::for_label("ISO-8859-1").unwrap();
+/// assert_eq!(charset.name(), "windows-1252");
+/// assert_eq!(Charset::for_label("ebcdic"), None);
+/// ```
+///
+/// [Encoding Standard]: https://encoding.spec.whatwg.org/#names-and-labels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Charset {
+    encoding: &'static Encoding,
+}
+
+impl Charset {
+    /// Returns the charset named by `label`, if any.
+    ///
+    /// As in HTML forms, the UTF-16 charsets are replaced by UTF-8.
+    pub fn for_label(label: &str) -> Option<Self> {
+        let encoding = Encoding::for_label(label.as_bytes())?;
+        Some(Charset {
+            encoding: encoding.output_encoding(),
+        })
+    }
+
+    /// Returns the canonical name of the charset.
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub(crate) fn is_utf8(&self) -> bool {
+        self.encoding == encoding_rs::UTF_8
+    }
+
+    /// Decodes a `name=value` pair, returning `None` for an empty pair.
+    pub(crate) fn decode_pair(
+        &self,
+        input: &[u8],
+        spaces: SpaceEncoding,
+    ) -> Option<(Cow<'static, str>, Cow<'static, str>)> {
+        let (name, value) = split_pair(input)?;
+        Some((self.decode(name, spaces), self.decode(value, spaces)))
+    }
+
+    fn decode(&self, input: &[u8], spaces: SpaceEncoding) -> Cow<'static, str> {
+        let bytes = unescape(input, spaces);
+        let (decoded, _) = self.encoding.decode_without_bom_handling(&bytes);
+        Cow::Owned(decoded.into_owned())
+    }
+
+    fn encode<'a>(&self, input: &'a str) -> Cow<'a, [u8]> {
+        self.encoding.encode(input).0
+    }
+
+    /// Returns the charset named by the first `_charset_` pair of `input`
+    /// that names one.
+    pub(crate) fn from_charset_field(input: &[u8]) -> Option<Self> {
+        input
+            .split(|&b| b == b'&')
+            .filter_map(|pair| {
+                let mut parts = pair.splitn(2, |&b| b == b'=');
+                let name = parts.next()?;
+                let value = parts.next().unwrap_or_default();
+                let spaces = SpaceEncoding::Plus;
+                if unescape(name, spaces) != b"_charset_" {
+                    return None;
+                }
+                let label = unescape(value, spaces);
+                Charset::for_label(&String::from_utf8_lossy(&label))
+            })
+            .next()
+    }
+
+    /// Returns the encoding override which makes a `form_urlencoded`
+    /// serializer encode into this charset.
+    pub(crate) fn encoding_override(&self) -> EncodingOverride<'static> {
+        macro_rules! encoders {
+            ($($name:ident,)*) => {
+                $(
+                    if self.encoding == encoding_rs::$name {
+                        return Some(&|input: &str| {
+                            encoding_rs::$name.encode(input).0
+                        });
+                    }
+                )*
+            };
+        }
+
+        // Every output encoding but UTF-8, which needs no override.
+        encoders! {
+            BIG5,
+            EUC_JP,
+            EUC_KR,
+            GB18030,
+            GBK,
+            IBM866,
+            ISO_2022_JP,
+            ISO_8859_2,
+            ISO_8859_3,
+            ISO_8859_4,
+            ISO_8859_5,
+            ISO_8859_6,
+            ISO_8859_7,
+            ISO_8859_8,
+            ISO_8859_8_I,
+            ISO_8859_10,
+            ISO_8859_13,
+            ISO_8859_14,
+            ISO_8859_15,
+            ISO_8859_16,
+            KOI8_R,
+            KOI8_U,
+            MACINTOSH,
+            SHIFT_JIS,
+            WINDOWS_874,
+            WINDOWS_1250,
+            WINDOWS_1251,
+            WINDOWS_1252,
+            WINDOWS_1253,
+            WINDOWS_1254,
+            WINDOWS_1255,
+            WINDOWS_1256,
+            WINDOWS_1257,
+            WINDOWS_1258,
+            X_MAC_CYRILLIC,
+            X_USER_DEFINED,
+        }
+        None
+    }
+}
+
+impl Default for Charset {
+    /// Returns UTF-8.
+    fn default() -> Self {
+        Charset {
+            encoding: encoding_rs::UTF_8,
+        }
+    }
+}
+
+/// How spaces are encoded.
+///
+/// In the `application/x-www-form-urlencoded` data sent by HTML forms, a
+/// space is encoded as `+`, whereas in the query strings of URLs as read by
+/// some RFC 3986 servers, `+` is a literal plus sign and a space is encoded
+/// as `%20`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpaceEncoding {
+    /// `+` is a space, the default.
+    #[default]
+    Plus,
+    /// `+` is a plus sign, and a space is `%20`.
+    Percent,
+}
+
+impl SpaceEncoding {
+    /// Encodes the spaces of `output`, the output of a `form_urlencoded`
+    /// serializer, which always encodes them as `+`.
+    ///
+    /// ```
+    /// use serde_urlencoded::SpaceEncoding;
+    ///
+    /// let output = serde_urlencoded::to_string(&[("q", "1 + 1")]).unwrap();
+    /// assert_eq!(output, "q=1+%2B+1");
+    /// assert_eq!(SpaceEncoding::Percent.reencode(output), "q=1%20%2B%201");
+    /// ```
+    pub fn reencode(self, output: String) -> String {
+        match self {
+            SpaceEncoding::Plus => output,
+            // Plus signs themselves are encoded as `%2B`.
+            SpaceEncoding::Percent => output.replace('+', "%20"),
+        }
+    }
+}
+
+/// Appends a pair whose key is encoded into `charset` and whose value is
+/// percent-encoded as is.
+///
+/// `form_urlencoded` only appends text, which is encoded with its encoding
+/// override before being percent-encoded, so the key and value are mapped to
+/// text with x-user-defined, which maps every byte to a distinct character,
+/// and mapped back to the same bytes by overriding the encoding for this
+/// pair only.
+pub(crate) fn append_raw_pair<Target: UrlEncodedTarget>(
+    urlencoder: &mut UrlEncodedSerializer<'_, Target>,
+    charset: Charset,
+    key: &str,
+    value: &[u8],
+) {
+    let to_text = |bytes: &[u8]| {
+        let (text, _) = X_USER_DEFINED.decode_without_bom_handling(bytes);
+        text.into_owned()
+    };
+    let key = to_text(&charset.encode(key));
+    let value = to_text(value);
+    urlencoder
+        .encoding_override(Some(&|input: &str| X_USER_DEFINED.encode(input).0))
+        .append_pair(&key, &value)
+        .encoding_override(charset.encoding_override());
+}
+
+/// Splits a `name=value` pair, returning `None` for an empty pair.
+pub(crate) fn split_pair(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    if input.is_empty() {
+        return None;
+    }
+    Some(match input.iter().position(|&b| b == b'=') {
+        Some(position) => (&input[..position], &input[position + 1..]),
+        None => (input, &[][..]),
+    })
+}
+
+/// Decodes percent-escapes, and replaces `+` with spaces if `spaces` is
+/// `SpaceEncoding::Plus`.
+pub(crate) fn unescape(input: &[u8], spaces: SpaceEncoding) -> Vec<u8> {
+    if spaces == SpaceEncoding::Percent {
+        return percent_decode(input).collect();
+    }
+    let replaced = input
+        .iter()
+        .map(|&b| if b == b'+' { b' ' } else { b })
+        .collect::<Vec<_>>();
+    percent_decode(&replaced).collect()
+}
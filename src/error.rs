@@ -0,0 +1,308 @@
+//! The error type shared by serialization and deserialization.
+
+use serde::{de, ser};
+use std::error;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::str;
+
+/// Errors returned during serialization and deserialization.
+///
+/// Besides its message, an error has a kind and, when known, the key of the
+/// pair that failed and its raw value, so that a failure can be reported for
+/// a specific field.
+///
+/// ```
+/// use serde_urlencoded::ErrorKind;
+///
+/// #[derive(Debug, serde_derive::Deserialize)]
+/// struct Person {
+///     name: String,
+///     age: u8,
+/// }
+///
+/// let err = serde_urlencoded::from_str::<Person>("name=Ann&age=old")
+///     .unwrap_err();
+/// assert_eq!(err.kind(), ErrorKind::Parse);
+/// assert_eq!(err.key(), Some("age"));
+/// assert_eq!(err.value(), Some("old"));
+///
+/// let err = serde_urlencoded::from_str::<Person>("name=Ann").unwrap_err();
+/// assert_eq!(err.kind(), ErrorKind::MissingField);
+/// assert_eq!(err.key(), Some("age"));
+/// assert_eq!(err.to_string(), "missing field `age`");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    inner: Box<ErrorImpl>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ErrorImpl {
+    kind: ErrorKind,
+    message: String,
+    key: Option<String>,
+    value: Option<String>,
+    span: Option<Range<usize>>,
+    path: Option<String>,
+    utf8: Option<str::Utf8Error>,
+}
+
+/// The kind of an error.
+///
+/// More kinds may be added in future versions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A value could not be parsed as the expected type, such as `abc` for
+    /// an integer, or does not name a known enum variant.
+    Parse,
+    /// A field of a struct is missing.
+    MissingField,
+    /// A field is not expected by a struct.
+    UnknownField,
+    /// A key appears more than once where it may appear only once.
+    DuplicateKey,
+    /// The data has a shape that is not supported, such as a sequence where
+    /// a single value is expected, or a nested sequence when serializing,
+    /// or a decoding option is set on a deserializer that cannot apply it.
+    Unsupported,
+    /// A value other than a map, a struct or a sequence of pairs was passed
+    /// to the top-level serializer.
+    TopLevel,
+    /// A map value was serialized before its key.
+    NoKey,
+    /// A pair was serialized with less or more than two elements.
+    PairState,
+    /// A bytes value is serialized as a key, or a key or value is decoded
+    /// with `Deserializer::strict_utf8`, and it is not valid UTF-8.
+    Utf8,
+    /// The input is not well-formed, see `Deserializer::strict_encoding` and
+    /// `Deserializer::sequence_gaps`.
+    Malformed,
+    /// A limit set with `Deserializer::limits` was exceeded.
+    LimitExceeded,
+    /// The input could not be read.
+    Io,
+    /// Any other error, usually raised by a `Serialize` or `Deserialize`
+    /// implementation.
+    Custom,
+}
+
+impl Error {
+    pub(crate) fn new<T: fmt::Display>(kind: ErrorKind, message: T) -> Self {
+        Error {
+            inner: Box::new(ErrorImpl {
+                kind,
+                message: message.to_string(),
+                key: None,
+                value: None,
+                span: None,
+                path: None,
+                utf8: None,
+            }),
+        }
+    }
+
+    pub(crate) fn unsupported() -> Self {
+        Error::new(ErrorKind::Unsupported, "unsupported value")
+    }
+
+    pub(crate) fn utf8(err: str::Utf8Error) -> Self {
+        let message = format_args!("invalid UTF-8: {}", err);
+        let mut error = Error::new(ErrorKind::Utf8, message);
+        error.inner.utf8 = Some(err);
+        error
+    }
+
+    /// Returns an error for an invalid UTF-8 sequence in the decoded key or
+    /// value of a pair.
+    pub(crate) fn utf8_in(part: &str, err: str::Utf8Error) -> Self {
+        let message = format_args!("invalid UTF-8 in {}: {}", part, err);
+        let mut error = Error::new(ErrorKind::Utf8, message);
+        error.inner.utf8 = Some(err);
+        error
+    }
+
+    pub(crate) fn io(err: io::Error) -> Self {
+        let message = format_args!("could not read input: {}", err);
+        Error::new(ErrorKind::Io, message)
+    }
+
+    /// Sets the key and raw value of the pair that failed, unless they were
+    /// already set closer to the failure.
+    pub(crate) fn at(mut self, key: &str, value: Option<&str>) -> Self {
+        if self.inner.key.is_none() {
+            self.inner.key = Some(key.to_owned());
+        }
+        match value {
+            Some(value) => self.at_value(value),
+            None => self,
+        }
+    }
+
+    pub(crate) fn at_value(mut self, value: &str) -> Self {
+        if self.inner.value.is_none() {
+            self.inner.value = Some(value.to_owned());
+        }
+        self
+    }
+
+    pub(crate) fn at_span(mut self, span: Option<Range<usize>>) -> Self {
+        if self.inner.span.is_none() {
+            self.inner.span = span;
+        }
+        self
+    }
+
+    /// Adds a field or map key to the front of the path of this error.
+    pub(crate) fn in_field(self, field: &str) -> Self {
+        self.in_path(field)
+    }
+
+    /// Adds a sequence index to the front of the path of this error.
+    pub(crate) fn in_index(self, index: usize) -> Self {
+        self.in_path(&format!("[{}]", index))
+    }
+
+    /// Sets the full path of the value that failed, unless a nested value
+    /// already set it.
+    pub(crate) fn at_path(mut self, path: &str) -> Self {
+        if self.inner.path.is_none() {
+            self.inner.path = Some(path.to_owned());
+        }
+        self
+    }
+
+    fn in_path(mut self, segment: &str) -> Self {
+        self.inner.path = Some(match self.inner.path {
+            None => segment.to_owned(),
+            Some(path) if path.starts_with('[') => segment.to_owned() + &path,
+            Some(path) => format!("{}.{}", segment, path),
+        });
+        self
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.inner.kind
+    }
+
+    /// Returns the key of the pair that failed to deserialize, or the name
+    /// of the missing, unknown or duplicate field, if known.
+    pub fn key(&self) -> Option<&str> {
+        self.inner.key.as_deref()
+    }
+
+    /// Returns the decoded value that failed to deserialize, if known.
+    pub fn value(&self) -> Option<&str> {
+        self.inner.value.as_deref()
+    }
+
+    /// Returns the path to the value that failed to deserialize, such as
+    /// `items[1]` or `user.address.zip`, if it is known.
+    ///
+    /// The path is also the prefix of the message of this error. It is not
+    /// known for the errors collected by
+    /// `Deserializer::deserialize_collecting_errors`.
+    ///
+    /// ```
+    /// #[derive(Debug, serde_derive::Deserialize)]
+    /// struct Order {
+    ///     items: Vec<u32>,
+    /// }
+    ///
+    /// let err = serde_urlencoded::from_str::<Order>("items[]=1&items[]=x")
+    ///     .unwrap_err();
+    /// assert_eq!(err.path(), Some("items[1]"));
+    /// assert_eq!(err.to_string(), "items[1]: invalid digit found in string");
+    /// ```
+    pub fn path(&self) -> Option<&str> {
+        self.inner.path.as_deref()
+    }
+
+    /// Returns the byte range in the input of the pair that failed to
+    /// deserialize, if known.
+    ///
+    /// ```
+    /// use serde_urlencoded::de::Deserializer;
+    /// use serde::Deserialize;
+    ///
+    /// let input = b"a=1&b=x&c=3";
+    /// let de = Deserializer::from_bytes(input);
+    /// let err = Vec::<(String, u32)>::deserialize(de).unwrap_err();
+    /// assert_eq!(err.span(), Some(4..7));
+    /// assert_eq!(&input[err.span().unwrap()], b"b=x");
+    /// ```
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.inner.span.clone()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inner.path {
+            Some(ref path) => write!(f, "{}: {}", path, self.inner.message),
+            None => self.inner.message.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    /// The lower-level source of this error, in the case of a `Utf8` error.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.utf8.as_ref().map(|err| err as _)
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(ErrorKind::Custom, msg)
+    }
+}
+
+// The messages are the ones of `serde::de::value::Error`.
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(ErrorKind::Custom, msg)
+    }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        let message = de::value::Error::invalid_type(unexp, exp);
+        Error::new(ErrorKind::Unsupported, message)
+    }
+
+    fn invalid_value(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        let message = de::value::Error::invalid_value(unexp, exp);
+        Error::new(ErrorKind::Parse, message)
+    }
+
+    fn invalid_length(len: usize, exp: &dyn de::Expected) -> Self {
+        let message = de::value::Error::invalid_length(len, exp);
+        Error::new(ErrorKind::Unsupported, message)
+    }
+
+    fn unknown_variant(
+        variant: &str,
+        expected: &'static [&'static str],
+    ) -> Self {
+        let message = de::value::Error::unknown_variant(variant, expected);
+        Error::new(ErrorKind::Parse, message).at_value(variant)
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        let message = de::value::Error::unknown_field(field, expected);
+        Error::new(ErrorKind::UnknownField, message).at(field, None)
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        let message = de::value::Error::missing_field(field);
+        Error::new(ErrorKind::MissingField, message).at(field, None)
+    }
+
+    fn duplicate_field(field: &'static str) -> Self {
+        let message = de::value::Error::duplicate_field(field);
+        Error::new(ErrorKind::DuplicateKey, message).at(field, None)
+    }
+}
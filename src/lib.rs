@@ -3,9 +3,15 @@
 #![warn(unused_extern_crates)]
 
 pub mod de;
+mod encoding;
+mod error;
 pub mod ser;
 
 #[doc(inline)]
 pub use crate::de::{from_bytes, from_reader, from_str, Deserializer};
 #[doc(inline)]
-pub use crate::ser::{to_string, Serializer};
+pub use crate::encoding::{Charset, SpaceEncoding};
+#[doc(inline)]
+pub use crate::error::{Error, ErrorKind};
+#[doc(inline)]
+pub use crate::ser::{to_string, to_string_with_spaces, Serializer};
@@ -5,12 +5,14 @@ mod pair;
 mod part;
 mod value;
 
+#[doc(inline)]
+pub use crate::error::{Error, ErrorKind};
+
+use crate::{Charset, SpaceEncoding};
 use form_urlencoded::Serializer as UrlEncodedSerializer;
 use form_urlencoded::Target as UrlEncodedTarget;
 use serde::ser;
 use std::borrow::Cow;
-use std::error;
-use std::fmt;
 use std::str;
 
 /// Serializes a value into a `application/x-www-form-urlencoded` `String` buffer.
@@ -33,17 +35,57 @@ pub fn to_string<T: ser::Serialize>(input: T) -> Result<String, Error> {
     Ok(urlencoder.finish())
 }
 
+/// Serializes a value like `to_string`, with spaces encoded as `spaces`.
+///
+/// ```
+/// use serde_urlencoded::SpaceEncoding;
+///
+/// let query = &[("q", "1 + 1")];
+/// assert_eq!(
+///     serde_urlencoded::to_string_with_spaces(query, SpaceEncoding::Percent),
+///     Ok("q=1%20%2B%201".to_owned()));
+/// ```
+pub fn to_string_with_spaces<T: ser::Serialize>(
+    input: T,
+    spaces: SpaceEncoding,
+) -> Result<String, Error> {
+    // Plus signs themselves are encoded as `%2B`, so any `+` is a space.
+    to_string(input).map(|output| spaces.reencode(output))
+}
+
 /// A serializer for the `application/x-www-form-urlencoded` format.
 ///
 /// * Supported top-level inputs are structs, maps and sequences of pairs,
-///   with or without a given length.
+///   with or without a given length. Structs and maps in top-level sequences
+///   are serialized as indexed groups such as `0[field]`.
+///
+/// * Top-level unit, newtype and struct variants are serialized as a tag
+///   pair naming the variant followed by the fields of the variant, as in
+///   `event=push&ref=main`, see `Serializer::enum_tag`.
 ///
 /// * Supported keys and values are integers, bytes (if convertible to strings),
 ///   unit structs and unit variants.
 ///
+/// * Bytes values, such as `serde_bytes` fields, are percent-encoded as they
+///   are, even if they are not valid UTF-8.
+///
+/// * Spaces are encoded as `+` by `form_urlencoded`, use
+///   `to_string_with_spaces` or `SpaceEncoding::reencode` on the output to
+///   encode them as `%20`.
+///
+/// * Nested structs and maps are serialized with their fields as
+///   `key[field]` pairs, recursively. Nested map keys containing `[` or `]`
+///   fail with an error of kind `ErrorKind::Unsupported`.
+///
+/// * Newtype, tuple and struct variants in values are serialized as if
+///   nested under their name, as in `shape[circle]=3`,
+///   `shape[point][0]=1&shape[point][1]=2` or `shape[rect][width]=4`.
+///
 /// * Newtype structs defer to their inner values.
 pub struct Serializer<'input, 'output, Target: UrlEncodedTarget> {
     urlencoder: &'output mut UrlEncodedSerializer<'input, Target>,
+    options: Options,
+    enum_tag: &'static str,
 }
 
 impl<'input, 'output, Target: 'output + UrlEncodedTarget>
@@ -53,45 +95,152 @@ impl<'input, 'output, Target: 'output + UrlEncodedTarget>
     pub fn new(
         urlencoder: &'output mut UrlEncodedSerializer<'input, Target>,
     ) -> Self {
-        Serializer { urlencoder }
+        Serializer {
+            urlencoder,
+            options: Options::default(),
+            enum_tag: "event",
+        }
     }
-}
 
-/// Errors returned during serializing to `application/x-www-form-urlencoded`.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Error {
-    Custom(Cow<'static, str>),
-    Utf8(str::Utf8Error),
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Error::Custom(ref msg) => msg.fmt(f),
-            Error::Utf8(ref err) => write!(f, "invalid UTF-8: {}", err),
-        }
+    /// Sets the way sequences of values are serialized, which defaults to
+    /// `ArrayStyle::Brackets`.
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_urlencoded::ser::{ArrayStyle, Serializer};
+    ///
+    /// let params = &[("tag", vec!["a", "b"])];
+    ///
+    /// let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    /// let serializer = Serializer::new(&mut urlencoder)
+    ///     .array_style(ArrayStyle::Repeat);
+    /// params.serialize(serializer).unwrap();
+    /// assert_eq!(urlencoder.finish(), "tag=a&tag=b");
+    ///
+    /// let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    /// let serializer = Serializer::new(&mut urlencoder)
+    ///     .array_style(ArrayStyle::Delimited(','));
+    /// params.serialize(serializer).unwrap();
+    /// assert_eq!(urlencoder.finish(), "tag=a%2Cb");
+    /// ```
+    pub fn array_style(mut self, style: ArrayStyle) -> Self {
+        self.options.array_style = style;
+        self
+    }
+
+    /// Encodes keys and values into `charset` instead of UTF-8, by setting
+    /// the encoding override of the underlying `form_urlencoded` serializer.
+    ///
+    /// The override is briefly replaced to serialize bytes values, and is
+    /// then reset according to this option, so the charset should be set
+    /// here rather than directly on the `form_urlencoded` serializer.
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_urlencoded::{Charset, Serializer};
+    ///
+    /// let charset = Charset::for_label("Shift_JIS").unwrap();
+    ///
+    /// let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    /// let serializer = Serializer::new(&mut urlencoder).charset(charset);
+    /// [("_charset_", charset.name()), ("name", "日")]
+    ///     .serialize(serializer)
+    ///     .unwrap();
+    /// assert_eq!(urlencoder.finish(), "_charset_=Shift_JIS&name=%93%FA");
+    /// ```
+    pub fn charset(mut self, charset: Charset) -> Self {
+        self.urlencoder
+            .encoding_override(charset.encoding_override());
+        self.options.charset = charset;
+        self
+    }
+
+    /// Serializes unit variants in values as their index in the enum,
+    /// rather than their name, as expected by clients that send numeric
+    /// codes. Keys and the tag of a top-level enum are still names.
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_urlencoded::Serializer;
+    ///
+    /// #[derive(serde_derive::Serialize)]
+    /// enum Status {
+    ///     Draft,
+    ///     Published,
+    /// }
+    ///
+    /// let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    /// let serializer = Serializer::new(&mut urlencoder).variant_indices(true);
+    /// [("status", Status::Published)].serialize(serializer).unwrap();
+    /// assert_eq!(urlencoder.finish(), "status=1");
+    /// ```
+    pub fn variant_indices(mut self, enabled: bool) -> Self {
+        self.options.variant_indices = enabled;
+        self
+    }
+
+    /// Sets the key of the pair naming the variant of a top-level enum,
+    /// which defaults to `event`.
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_urlencoded::Serializer;
+    ///
+    /// #[derive(serde_derive::Serialize)]
+    /// #[serde(rename_all = "snake_case")]
+    /// enum Action {
+    ///     Rename { name: String },
+    /// }
+    ///
+    /// let action = Action::Rename { name: "notes".to_owned() };
+    ///
+    /// let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    /// let serializer = Serializer::new(&mut urlencoder).enum_tag("action");
+    /// action.serialize(serializer).unwrap();
+    /// assert_eq!(urlencoder.finish(), "action=rename&name=notes");
+    /// ```
+    pub fn enum_tag(mut self, tag: &'static str) -> Self {
+        self.enum_tag = tag;
+        self
     }
 }
 
-impl error::Error for Error {
-    /// The lower-level source of this error, in the case of a `Utf8` error.
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match *self {
-            Error::Custom(_) => None,
-            Error::Utf8(ref err) => Some(err),
-        }
-    }
+/// The way sequences of values are serialized.
+///
+/// Sequences of structs or maps are always serialized as indexed groups such
+/// as `items[0][name]=x`, regardless of the style. A sequence mixing groups
+/// with values, such as unit and struct variants of the same enum, fails with
+/// an error of kind `ErrorKind::Unsupported` unless the style is `Indexed`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrayStyle {
+    /// `tag[]=a&tag[]=b`, the default.
+    #[default]
+    Brackets,
+    /// `tag=a&tag=b`.
+    Repeat,
+    /// `tag[0]=a&tag[1]=b`.
+    Indexed,
+    /// `tag=a,b` when the delimiter is `,`.
+    ///
+    /// An element containing the delimiter fails with an error of kind
+    /// `ErrorKind::Unsupported`, as it could not be told apart from two
+    /// elements when deserializing.
+    Delimited(char),
 }
 
-impl ser::Error for Error {
-    fn custom<T: fmt::Display>(msg: T) -> Self {
-        Error::Custom(format!("{}", msg).into())
-    }
+/// Options shared by every sink of a `Serializer`.
+#[derive(Clone, Copy, Default)]
+struct Options {
+    array_style: ArrayStyle,
+    charset: Charset,
+    variant_indices: bool,
 }
 
 /// Sequence serializer.
 pub struct SeqSerializer<'input, 'output, Target: UrlEncodedTarget> {
     urlencoder: &'output mut UrlEncodedSerializer<'input, Target>,
+    options: Options,
+    index: usize,
 }
 
 /// Tuple serializer.
@@ -99,6 +248,8 @@ pub struct SeqSerializer<'input, 'output, Target: UrlEncodedTarget> {
 /// Mostly used for arrays.
 pub struct TupleSerializer<'input, 'output, Target: UrlEncodedTarget> {
     urlencoder: &'output mut UrlEncodedSerializer<'input, Target>,
+    options: Options,
+    index: usize,
 }
 
 /// Tuple struct serializer.
@@ -118,19 +269,19 @@ pub struct TupleVariantSerializer<'input, 'output, T: UrlEncodedTarget> {
 /// Map serializer.
 pub struct MapSerializer<'input, 'output, Target: UrlEncodedTarget> {
     urlencoder: &'output mut UrlEncodedSerializer<'input, Target>,
+    options: Options,
     key: Option<Cow<'static, str>>,
 }
 
 /// Struct serializer.
 pub struct StructSerializer<'input, 'output, Target: UrlEncodedTarget> {
     urlencoder: &'output mut UrlEncodedSerializer<'input, Target>,
+    options: Options,
 }
 
 /// Struct variant serializer.
-///
-/// Never instantiated, struct variants are not supported.
 pub struct StructVariantSerializer<'input, 'output, T: UrlEncodedTarget> {
-    inner: ser::Impossible<&'output mut UrlEncodedSerializer<'input, T>, Error>,
+    inner: StructSerializer<'input, 'output, T>,
 }
 
 impl<'input, 'output, Target> ser::Serializer
@@ -233,14 +384,15 @@ where
         Ok(self.urlencoder)
     }
 
-    /// Returns an error.
+    /// Serializes the tag pair naming the variant.
     fn serialize_unit_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, Error> {
-        Err(Error::top_level())
+        self.urlencoder.append_pair(self.enum_tag, variant);
+        Ok(self.urlencoder)
     }
 
     /// Serializes the inner value, ignoring the newtype name.
@@ -252,15 +404,16 @@ where
         value.serialize(self)
     }
 
-    /// Returns an error.
+    /// Serializes the tag pair naming the variant, then the inner value.
     fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
-        _value: &T,
+        variant: &'static str,
+        value: &T,
     ) -> Result<Self::Ok, Error> {
-        Err(Error::top_level())
+        self.urlencoder.append_pair(self.enum_tag, variant);
+        value.serialize(self)
     }
 
     /// Returns `Ok`.
@@ -283,6 +436,8 @@ where
     ) -> Result<Self::SerializeSeq, Error> {
         Ok(SeqSerializer {
             urlencoder: self.urlencoder,
+            options: self.options,
+            index: 0,
         })
     }
 
@@ -293,6 +448,8 @@ where
     ) -> Result<Self::SerializeTuple, Error> {
         Ok(TupleSerializer {
             urlencoder: self.urlencoder,
+            options: self.options,
+            index: 0,
         })
     }
 
@@ -323,6 +480,7 @@ where
     ) -> Result<Self::SerializeMap, Error> {
         Ok(MapSerializer {
             urlencoder: self.urlencoder,
+            options: self.options,
             key: None,
         })
     }
@@ -335,18 +493,26 @@ where
     ) -> Result<Self::SerializeStruct, Error> {
         Ok(StructSerializer {
             urlencoder: self.urlencoder,
+            options: self.options,
         })
     }
 
-    /// Returns an error.
+    /// Serializes the tag pair naming the variant, then the fields as those
+    /// of a struct.
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Error> {
-        Err(Error::top_level())
+        self.urlencoder.append_pair(self.enum_tag, variant);
+        Ok(StructVariantSerializer {
+            inner: StructSerializer {
+                urlencoder: self.urlencoder,
+                options: self.options,
+            },
+        })
     }
 }
 
@@ -362,7 +528,13 @@ where
         &mut self,
         value: &T,
     ) -> Result<(), Error> {
-        value.serialize(pair::PairSerializer::new(self.urlencoder))
+        let pair = pair::PairSerializer::new(
+            self.urlencoder,
+            self.index,
+            self.options,
+        );
+        self.index += 1;
+        value.serialize(pair)
     }
 
     fn end(self) -> Result<Self::Ok, Error> {
@@ -382,7 +554,13 @@ where
         &mut self,
         value: &T,
     ) -> Result<(), Error> {
-        value.serialize(pair::PairSerializer::new(self.urlencoder))
+        let pair = pair::PairSerializer::new(
+            self.urlencoder,
+            self.index,
+            self.options,
+        );
+        self.index += 1;
+        value.serialize(pair)
     }
 
     fn end(self) -> Result<Self::Ok, Error> {
@@ -447,8 +625,11 @@ where
         value: &V,
     ) -> Result<(), Error> {
         let key_sink = key::KeySink::new(|key| {
-            let value_sink = value::ValueSink::new(self.urlencoder, &key);
-            value.serialize(part::PartSerializer::new(value_sink))?;
+            let value_sink =
+                value::ValueSink::new(self.urlencoder, &key, self.options);
+            value
+                .serialize(part::PartSerializer::new(value_sink))
+                .map_err(|e| e.at_path(&key))?;
             self.key = None;
             Ok(())
         });
@@ -472,8 +653,11 @@ where
     ) -> Result<(), Error> {
         {
             let key = self.key.as_ref().ok_or_else(Error::no_key)?;
-            let value_sink = value::ValueSink::new(self.urlencoder, &key);
-            value.serialize(part::PartSerializer::new(value_sink))?;
+            let value_sink =
+                value::ValueSink::new(self.urlencoder, key, self.options);
+            value
+                .serialize(part::PartSerializer::new(value_sink))
+                .map_err(|e| e.at_path(key))?;
         }
         self.key = None;
         Ok(())
@@ -497,8 +681,11 @@ where
         key: &'static str,
         value: &T,
     ) -> Result<(), Error> {
-        let value_sink = value::ValueSink::new(self.urlencoder, key);
-        value.serialize(part::PartSerializer::new(value_sink))
+        let value_sink =
+            value::ValueSink::new(self.urlencoder, key, self.options);
+        value
+            .serialize(part::PartSerializer::new(value_sink))
+            .map_err(|e| e.at_path(key))
     }
 
     fn end(self) -> Result<Self::Ok, Error> {
@@ -519,22 +706,22 @@ where
         key: &'static str,
         value: &T,
     ) -> Result<(), Error> {
-        self.inner.serialize_field(key, value)
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Error> {
-        self.inner.end()
+        ser::SerializeStruct::end(self.inner)
     }
 }
 
 impl Error {
     fn top_level() -> Self {
         let msg = "top-level serializer supports only maps and structs";
-        Error::Custom(msg.into())
+        Error::new(ErrorKind::TopLevel, msg)
     }
 
-    fn no_key() -> Self {
+    pub(crate) fn no_key() -> Self {
         let msg = "tried to serialize a value before serializing key";
-        Error::Custom(msg.into())
+        Error::new(ErrorKind::NoKey, msg)
     }
 }
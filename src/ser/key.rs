@@ -1,5 +1,5 @@
 use crate::ser::part::Sink;
-use crate::ser::Error;
+use crate::ser::{Error, ErrorKind};
 use serde::ser;
 use std::borrow::Cow;
 use std::ops::Deref;
@@ -48,6 +48,9 @@ where
 {
     type Ok = Ok;
     type SerializeSeq = ser::Impossible<Self::Ok, Error>;
+    type SerializeMap = ser::Impossible<Self::Ok, Error>;
+    type SerializeStruct = ser::Impossible<Self::Ok, Error>;
+    type SerializeVariant = ser::Impossible<Self::Ok, Error>;
 
     fn serialize_static_str(self, value: &'static str) -> Result<Ok, Error> {
         (self.end)(Key::Static(value))
@@ -76,7 +79,30 @@ where
         Err(self.unsupported())
     }
 
+    fn serialize_map(self) -> Result<Self::SerializeMap, Error> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_struct(self) -> Result<Self::SerializeStruct, Error> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Ok, Error> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_variant(
+        self,
+        _variant: &'static str,
+    ) -> Result<Self::SerializeVariant, Error> {
+        Err(self.unsupported())
+    }
+
     fn unsupported(self) -> Error {
-        Error::Custom("unsupported key".into())
+        Error::new(ErrorKind::Unsupported, "unsupported key")
     }
 }
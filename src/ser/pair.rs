@@ -1,7 +1,7 @@
 use crate::ser::key::KeySink;
 use crate::ser::part::PartSerializer;
-use crate::ser::value::ValueSink;
-use crate::ser::Error;
+use crate::ser::value::{NestedSerializer, ValueSink};
+use crate::ser::{Error, ErrorKind, Options};
 use form_urlencoded::Serializer as UrlEncodedSerializer;
 use form_urlencoded::Target as UrlEncodedTarget;
 use serde::ser;
@@ -10,6 +10,8 @@ use std::mem;
 
 pub struct PairSerializer<'input, 'target, Target: UrlEncodedTarget> {
     urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
+    index: usize,
+    options: Options,
     state: PairState,
 }
 
@@ -17,17 +19,24 @@ impl<'input, 'target, Target> PairSerializer<'input, 'target, Target>
 where
     Target: 'target + UrlEncodedTarget,
 {
+    /// Returns a new `PairSerializer` for the element at `index` in a
+    /// top-level sequence.
     pub fn new(
         urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
+        index: usize,
+        options: Options,
     ) -> Self {
         PairSerializer {
             urlencoder,
+            index,
+            options,
             state: PairState::WaitingForKey,
         }
     }
 }
 
-impl<'target, Target> ser::Serializer for PairSerializer<'_, 'target, Target>
+impl<'input, 'target, Target> ser::Serializer
+    for PairSerializer<'input, 'target, Target>
 where
     Target: 'target + UrlEncodedTarget,
 {
@@ -37,8 +46,8 @@ where
     type SerializeTuple = Self;
     type SerializeTupleStruct = ser::Impossible<(), Error>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
-    type SerializeMap = ser::Impossible<(), Error>;
-    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeMap = NestedSerializer<'input, 'target, Target>;
+    type SerializeStruct = NestedSerializer<'input, 'target, Target>;
     type SerializeStructVariant = ser::Impossible<(), Error>;
 
     fn serialize_bool(self, _v: bool) -> Result<(), Error> {
@@ -180,7 +189,14 @@ where
         self,
         _len: Option<usize>,
     ) -> Result<Self::SerializeMap, Error> {
-        Err(Error::unsupported_pair())
+        let key = self.index.to_string();
+        let path = format!("[{}]", key);
+        Ok(NestedSerializer::new(
+            self.urlencoder,
+            key,
+            path,
+            self.options,
+        ))
     }
 
     fn serialize_struct(
@@ -188,7 +204,14 @@ where
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Error> {
-        Err(Error::unsupported_pair())
+        let key = self.index.to_string();
+        let path = format!("[{}]", key);
+        Ok(NestedSerializer::new(
+            self.urlencoder,
+            key,
+            path,
+            self.options,
+        ))
     }
 
     fn serialize_struct_variant(
@@ -225,9 +248,12 @@ where
             }
             PairState::WaitingForValue { key } => {
                 let result = {
-                    let value_sink = ValueSink::new(self.urlencoder, &key);
+                    let value_sink =
+                        ValueSink::new(self.urlencoder, &key, self.options);
                     let value_serializer = PartSerializer::new(value_sink);
-                    value.serialize(value_serializer)
+                    value
+                        .serialize(value_serializer)
+                        .map_err(|e| e.at_path(&key))
                 };
                 if result.is_ok() {
                     self.state = PairState::Done;
@@ -257,14 +283,20 @@ enum PairState {
 
 impl Error {
     fn done() -> Self {
-        Error::Custom("this pair has already been serialized".into())
+        Error::new(
+            ErrorKind::PairState,
+            "this pair has already been serialized",
+        )
     }
 
     fn not_done() -> Self {
-        Error::Custom("this pair has not yet been serialized".into())
+        Error::new(
+            ErrorKind::PairState,
+            "this pair has not yet been serialized",
+        )
     }
 
     fn unsupported_pair() -> Self {
-        Error::Custom("unsupported pair".into())
+        Error::new(ErrorKind::Unsupported, "unsupported pair")
     }
 }
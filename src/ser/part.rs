@@ -15,6 +15,10 @@ impl<S: Sink> PartSerializer<S> {
 pub trait Sink: Sized {
     type Ok;
     type SerializeSeq: ser::SerializeSeq<Ok = Self::Ok, Error = Error>;
+    type SerializeMap: ser::SerializeMap<Ok = Self::Ok, Error = Error>;
+    type SerializeStruct: ser::SerializeStruct<Ok = Self::Ok, Error = Error>;
+    type SerializeVariant: ser::SerializeTupleVariant<Ok = Self::Ok, Error = Error>
+        + ser::SerializeStructVariant<Ok = Self::Ok, Error = Error>;
 
     fn serialize_static_str(
         self,
@@ -22,7 +26,24 @@ pub trait Sink: Sized {
     ) -> Result<Self::Ok, Error>;
 
     fn serialize_str(self, value: &str) -> Result<Self::Ok, Error>;
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok, Error> {
+        match str::from_utf8(value) {
+            Ok(value) => self.serialize_str(value),
+            Err(err) => Err(Error::utf8(err)),
+        }
+    }
+
     fn serialize_string(self, value: String) -> Result<Self::Ok, Error>;
+
+    fn serialize_unit_variant(
+        self,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        self.serialize_static_str(variant)
+    }
+
     fn serialize_none(self) -> Result<Self::Ok, Error>;
 
     fn serialize_some<T: ?Sized + ser::Serialize>(
@@ -31,6 +52,20 @@ pub trait Sink: Sized {
     ) -> Result<Self::Ok, Error>;
 
     fn serialize_seq(self) -> Result<Self::SerializeSeq, Error>;
+    fn serialize_map(self) -> Result<Self::SerializeMap, Error>;
+    fn serialize_struct(self) -> Result<Self::SerializeStruct, Error>;
+
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error>;
+
+    /// Returns the serializer of the fields of a tuple or struct variant.
+    fn serialize_variant(
+        self,
+        variant: &'static str,
+    ) -> Result<Self::SerializeVariant, Error>;
 
     fn unsupported(self) -> Error;
 }
@@ -41,10 +76,10 @@ impl<S: Sink> ser::Serializer for PartSerializer<S> {
     type SerializeSeq = S::SerializeSeq;
     type SerializeTuple = ser::Impossible<S::Ok, Error>;
     type SerializeTupleStruct = ser::Impossible<S::Ok, Error>;
-    type SerializeTupleVariant = ser::Impossible<S::Ok, Error>;
-    type SerializeMap = ser::Impossible<S::Ok, Error>;
-    type SerializeStruct = ser::Impossible<S::Ok, Error>;
-    type SerializeStructVariant = ser::Impossible<S::Ok, Error>;
+    type SerializeTupleVariant = S::SerializeVariant;
+    type SerializeMap = S::SerializeMap;
+    type SerializeStruct = S::SerializeStruct;
+    type SerializeStructVariant = S::SerializeVariant;
 
     fn serialize_bool(self, v: bool) -> Result<S::Ok, Error> {
         self.sink
@@ -100,10 +135,7 @@ impl<S: Sink> ser::Serializer for PartSerializer<S> {
     }
 
     fn serialize_bytes(self, value: &[u8]) -> Result<S::Ok, Error> {
-        match str::from_utf8(value) {
-            Ok(value) => self.sink.serialize_str(value),
-            Err(err) => Err(Error::Utf8(err)),
-        }
+        self.sink.serialize_bytes(value)
     }
 
     fn serialize_unit(self) -> Result<S::Ok, Error> {
@@ -117,10 +149,10 @@ impl<S: Sink> ser::Serializer for PartSerializer<S> {
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
     ) -> Result<S::Ok, Error> {
-        self.sink.serialize_static_str(variant)
+        self.sink.serialize_unit_variant(variant_index, variant)
     }
 
     fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(
@@ -135,10 +167,10 @@ impl<S: Sink> ser::Serializer for PartSerializer<S> {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
-        _value: &T,
+        variant: &'static str,
+        value: &T,
     ) -> Result<S::Ok, Error> {
-        Err(self.sink.unsupported())
+        self.sink.serialize_newtype_variant(variant, value)
     }
 
     fn serialize_none(self) -> Result<S::Ok, Error> {
@@ -178,17 +210,17 @@ impl<S: Sink> ser::Serializer for PartSerializer<S> {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Error> {
-        Err(self.sink.unsupported())
+        self.sink.serialize_variant(variant)
     }
 
     fn serialize_map(
         self,
         _len: Option<usize>,
     ) -> Result<Self::SerializeMap, Error> {
-        Err(self.sink.unsupported())
+        self.sink.serialize_map()
     }
 
     fn serialize_struct(
@@ -196,17 +228,17 @@ impl<S: Sink> ser::Serializer for PartSerializer<S> {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Error> {
-        Err(self.sink.unsupported())
+        self.sink.serialize_struct()
     }
 
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Error> {
-        Err(self.sink.unsupported())
+        self.sink.serialize_variant(variant)
     }
 }
 
@@ -1,17 +1,61 @@
+use crate::encoding::append_raw_pair;
+use crate::ser::key::KeySink;
 use crate::ser::part::{PartSerializer, Sink};
-use crate::ser::Error;
+use crate::ser::{ArrayStyle, Error, ErrorKind, Options};
 use form_urlencoded::Serializer as UrlEncodedSerializer;
 use form_urlencoded::Target as UrlEncodedTarget;
 use serde::ser;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
 use std::str;
 
+/// The path of a value in the serialized data, such as `items[1]` or
+/// `user.address.zip`, used in errors.
+#[derive(Clone, Copy)]
+pub enum Path<'a> {
+    Key(&'a str),
+    Field(&'a str, &'a str),
+    Index(&'a Path<'a>, usize),
+}
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Path::Key(key) => key.fmt(f),
+            Path::Field(parent, field) => write!(f, "{}.{}", parent, field),
+            Path::Index(parent, index) => write!(f, "{}[{}]", parent, index),
+        }
+    }
+}
+
 pub struct ValueSink<'input, 'key, 'target, Target>
 where
     Target: UrlEncodedTarget,
 {
     urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
     key: &'key str,
+    path: Path<'key>,
+    options: Options,
     nested: bool,
+    /// The index of the next element when serializing a sequence, or the
+    /// index of this value in its parent sequence when `nested` is set.
+    index: usize,
+    /// The elements of a sequence serialized with `ArrayStyle::Delimited`.
+    pieces: Vec<String>,
+    /// Whether the elements of a sequence written so far are values or
+    /// groups.
+    elements: Cell<Option<Element>>,
+    /// The `elements` of the parent sequence when `nested` is set.
+    parent: Option<&'key Cell<Option<Element>>>,
+}
+
+/// How an element of a sequence is written, as a value like `key[]=a` or as
+/// an indexed group like `key[0][field]=a`.
+#[derive(Clone, Copy, PartialEq)]
+enum Element {
+    Value,
+    Group,
 }
 
 impl<'input, 'key, 'target, Target> ValueSink<'input, 'key, 'target, Target>
@@ -21,29 +65,94 @@ where
     pub fn new(
         urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
         key: &'key str,
+        options: Options,
+    ) -> Self {
+        ValueSink::with_path(urlencoder, key, Path::Key(key), options)
+    }
+
+    fn with_path(
+        urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
+        key: &'key str,
+        path: Path<'key>,
+        options: Options,
     ) -> Self {
         ValueSink {
             urlencoder,
             key,
+            path,
+            options,
             nested: false,
+            index: 0,
+            pieces: vec![],
+            elements: Cell::new(None),
+            parent: None,
+        }
+    }
+
+    fn pair_key(&self) -> Cow<'key, str> {
+        match self.options.array_style {
+            _ if !self.nested => Cow::Borrowed(self.key),
+            ArrayStyle::Brackets => format!("{}[]", self.key).into(),
+            ArrayStyle::Indexed => {
+                format!("{}[{}]", self.key, self.index).into()
+            }
+            ArrayStyle::Repeat | ArrayStyle::Delimited(_) => {
+                Cow::Borrowed(self.key)
+            }
+        }
+    }
+
+    /// Checks that the elements of a sequence are either all values or all
+    /// groups, as only indexed values can be read back along with groups.
+    fn check_element(&self, element: Element) -> Result<(), Error> {
+        let parent = match self.parent {
+            Some(_) if self.options.array_style == ArrayStyle::Indexed => {
+                return Ok(());
+            }
+            Some(parent) => parent,
+            None => return Ok(()),
+        };
+        match parent.get() {
+            Some(elements) if elements != element => {
+                let message = format_args!(
+                    "`{}` mixes values and groups, which requires \
+                     `ArrayStyle::Indexed`",
+                    self.key
+                );
+                Err(Error::new(ErrorKind::Unsupported, message))
+            }
+            _ => {
+                parent.set(Some(element));
+                Ok(())
+            }
         }
     }
 }
 
-impl<'target, Target> Sink for ValueSink<'_, '_, 'target, Target>
+impl<'input, 'target, Target> Sink for ValueSink<'input, '_, 'target, Target>
 where
     Target: 'target + UrlEncodedTarget,
 {
     type Ok = ();
     type SerializeSeq = Self;
+    type SerializeMap = NestedSerializer<'input, 'target, Target>;
+    type SerializeStruct = NestedSerializer<'input, 'target, Target>;
+    type SerializeVariant = NestedSerializer<'input, 'target, Target>;
 
     fn serialize_str(self, value: &str) -> Result<(), Error> {
-        if self.nested {
-            self.urlencoder
-                .append_pair(&format!("{}[]", self.key), value);
-        } else {
-            self.urlencoder.append_pair(self.key, value);
-        }
+        self.check_element(Element::Value)?;
+        let key = self.pair_key();
+        self.urlencoder.append_pair(&key, value);
+
+        Ok(())
+    }
+
+    /// Percent-encodes the bytes as they are, rather than as text in the
+    /// charset of the serializer, so that binary values are left intact.
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), Error> {
+        self.check_element(Element::Value)?;
+        let key = self.pair_key();
+        append_raw_pair(self.urlencoder, self.options.charset, &key, value);
 
         Ok(())
     }
@@ -56,6 +165,18 @@ where
         self.serialize_str(&value)
     }
 
+    fn serialize_unit_variant(
+        self,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        if self.options.variant_indices {
+            self.serialize_string(variant_index.to_string())
+        } else {
+            self.serialize_str(variant)
+        }
+    }
+
     fn serialize_none(self) -> Result<Self::Ok, Error> {
         Ok(())
     }
@@ -75,8 +196,53 @@ where
         }
     }
 
+    fn serialize_map(self) -> Result<Self::SerializeMap, Error> {
+        self.serialize_struct()
+    }
+
+    fn serialize_struct(self) -> Result<Self::SerializeStruct, Error> {
+        self.check_element(Element::Group)?;
+        let key = if self.nested {
+            format!("{}[{}]", self.key, self.index)
+        } else {
+            self.key.to_owned()
+        };
+        let path = self.path.to_string();
+        Ok(NestedSerializer::new(
+            self.urlencoder,
+            key,
+            path,
+            self.options,
+        ))
+    }
+
+    /// Serializes the variant as the single field of a nested map, as in
+    /// `shape[circle]=3`.
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let mut nested = self.serialize_struct()?;
+        nested.serialize_field_value(variant, value)
+    }
+
+    /// Serializes the fields of the variant in a nested map under its name,
+    /// as in `shape[circle][radius]=3` or `shape[point][0]=1`.
+    fn serialize_variant(
+        self,
+        variant: &'static str,
+    ) -> Result<Self::SerializeVariant, Error> {
+        let nested = self.serialize_struct()?;
+        Ok(NestedSerializer {
+            key: format!("{}[{}]", nested.key, variant),
+            path: Path::Field(&nested.path, variant).to_string(),
+            ..nested
+        })
+    }
+
     fn unsupported(self) -> Error {
-        Error::Custom("unsupported value".into())
+        Error::unsupported()
     }
 }
 
@@ -87,21 +253,308 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(
+    fn serialize_element<T: ?Sized + ser::Serialize>(
         &mut self,
         value: &T,
-    ) -> Result<(), Self::Error>
-    where
-        T: ser::Serialize,
-    {
-        value.serialize(PartSerializer::new(ValueSink {
+    ) -> Result<(), Self::Error> {
+        let index = self.index;
+        self.index += 1;
+        let path = Path::Index(&self.path, index);
+        let element = ValueSink {
             urlencoder: self.urlencoder,
             key: self.key,
+            path,
+            options: self.options,
             nested: true,
-        }))
+            index,
+            pieces: vec![],
+            elements: Cell::new(None),
+            parent: Some(&self.elements),
+        };
+        let result = if let ArrayStyle::Delimited(_) = self.options.array_style
+        {
+            let sink = JoinSink {
+                pieces: &mut self.pieces,
+                element,
+            };
+            value.serialize(PartSerializer::new(sink))
+        } else {
+            value.serialize(PartSerializer::new(element))
+        };
+        result.map_err(|e| e.at_path(&path.to_string()))
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        if let ArrayStyle::Delimited(delimiter) = self.options.array_style {
+            if !self.pieces.is_empty() {
+                let value =
+                    self.pieces.join(delimiter.encode_utf8(&mut [0; 4]));
+                self.urlencoder.append_pair(self.key, &value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the elements of a sequence serialized as a single delimited
+/// value.
+///
+/// Structs, maps and variants with fields cannot be joined, and are
+/// serialized by `element` as indexed groups instead.
+struct JoinSink<'pieces, 'input, 'key, 'target, Target>
+where
+    Target: UrlEncodedTarget,
+{
+    pieces: &'pieces mut Vec<String>,
+    element: ValueSink<'input, 'key, 'target, Target>,
+}
+
+impl<'input, 'target, Target> Sink for JoinSink<'_, 'input, '_, 'target, Target>
+where
+    Target: 'target + UrlEncodedTarget,
+{
+    type Ok = ();
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeMap = NestedSerializer<'input, 'target, Target>;
+    type SerializeStruct = NestedSerializer<'input, 'target, Target>;
+    type SerializeVariant = NestedSerializer<'input, 'target, Target>;
+
+    fn serialize_static_str(self, value: &'static str) -> Result<(), Error> {
+        self.serialize_str(value)
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Error> {
+        self.serialize_string(value.to_owned())
+    }
+
+    fn serialize_string(self, value: String) -> Result<(), Error> {
+        if let ArrayStyle::Delimited(delimiter) =
+            self.element.options.array_style
+        {
+            if value.contains(delimiter) {
+                let message = format_args!(
+                    "element `{}` contains the delimiter `{}`",
+                    value, delimiter
+                );
+                return Err(Error::new(ErrorKind::Unsupported, message));
+            }
+        }
+        self.element.check_element(Element::Value)?;
+        self.pieces.push(value);
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        if self.element.options.variant_indices {
+            self.serialize_string(variant_index.to_string())
+        } else {
+            self.serialize_str(variant)
+        }
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + ser::Serialize>(
+        self,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(PartSerializer::new(self))
+    }
+
+    fn serialize_seq(self) -> Result<Self::SerializeSeq, Error> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_map(self) -> Result<Self::SerializeMap, Error> {
+        self.element.serialize_map()
+    }
+
+    fn serialize_struct(self) -> Result<Self::SerializeStruct, Error> {
+        self.element.serialize_struct()
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.element.serialize_newtype_variant(variant, value)
+    }
+
+    fn serialize_variant(
+        self,
+        variant: &'static str,
+    ) -> Result<Self::SerializeVariant, Error> {
+        self.element.serialize_variant(variant)
+    }
+
+    fn unsupported(self) -> Error {
+        Error::unsupported()
+    }
+}
+
+/// Serializes the fields of a nested struct or map as `key[field]` pairs.
+///
+/// Structs and maps in sequences are serialized as indexed groups such as
+/// `key[0][field]`.
+pub struct NestedSerializer<'input, 'target, Target>
+where
+    Target: UrlEncodedTarget,
+{
+    urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
+    key: String,
+    path: String,
+    options: Options,
+    field: Option<String>,
+    /// The index of the next field of a tuple variant.
+    index: usize,
+}
+
+impl<'input, 'target, Target> NestedSerializer<'input, 'target, Target>
+where
+    Target: 'target + UrlEncodedTarget,
+{
+    pub fn new(
+        urlencoder: &'target mut UrlEncodedSerializer<'input, Target>,
+        key: String,
+        path: String,
+        options: Options,
+    ) -> Self {
+        NestedSerializer {
+            urlencoder,
+            key,
+            path,
+            options,
+            field: None,
+            index: 0,
+        }
+    }
+
+    fn serialize_field_value<T: ?Sized + ser::Serialize>(
+        &mut self,
+        field: &str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let key = format!("{}[{}]", self.key, field);
+        let path = Path::Field(&self.path, field);
+        value
+            .serialize(PartSerializer::new(ValueSink::with_path(
+                self.urlencoder,
+                &key,
+                path,
+                self.options,
+            )))
+            .map_err(|e| e.at_path(&path.to_string()))
+    }
+}
+
+impl<'target, Target> ser::SerializeStruct
+    for NestedSerializer<'_, 'target, Target>
+where
+    Target: 'target + UrlEncodedTarget,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_field_value(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'target, Target> ser::SerializeMap
+    for NestedSerializer<'_, 'target, Target>
+where
+    Target: 'target + UrlEncodedTarget,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &T,
+    ) -> Result<(), Error> {
+        // A bracket would change the nesting of the key when read back.
+        let key_sink = KeySink::new(|key| {
+            if key.contains(&['[', ']'][..]) {
+                let message =
+                    format_args!("map key `{}` contains a bracket", &*key);
+                return Err(Error::new(ErrorKind::Unsupported, message));
+            }
+            Ok((*key).to_owned())
+        });
+        let field = key
+            .serialize(PartSerializer::new(key_sink))
+            .map_err(|e| e.at_path(&self.path))?;
+        self.field = Some(field);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + ser::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        let field = self.field.take().ok_or_else(Error::no_key)?;
+        self.serialize_field_value(&field, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'target, Target> ser::SerializeTupleVariant
+    for NestedSerializer<'_, 'target, Target>
+where
+    Target: 'target + UrlEncodedTarget,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        let index = self.index.to_string();
+        self.index += 1;
+        self.serialize_field_value(&index, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'target, Target> ser::SerializeStructVariant
+    for NestedSerializer<'_, 'target, Target>
+where
+    Target: 'target + UrlEncodedTarget,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_field_value(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
         Ok(())
     }
 }
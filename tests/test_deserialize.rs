@@ -1,5 +1,10 @@
 use matches::assert_matches;
+use serde::de::Deserialize;
 use serde_derive::Deserialize;
+use serde_urlencoded::de::{
+    DuplicateKeys, Error, ErrorKind, Limits, SequenceGaps, VariantMatching,
+};
+use serde_urlencoded::{Charset, Deserializer, SpaceEncoding};
 
 #[derive(Deserialize, Debug, PartialEq)]
 struct NewType<T>(T);
@@ -216,7 +221,7 @@ fn deserialize_list_of_enum() {
     );
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, PartialEq, serde_derive::Serialize)]
 struct Wrapper<T> {
     item: T,
 }
@@ -297,3 +302,1555 @@ fn deserialize_numlist() {
         })
     );
 }
+
+#[test]
+fn deserialize_struct_list() {
+    assert_eq!(
+        serde_urlencoded::from_str("list[]=hello&list[]=world"),
+        Ok(Struct {
+            list: vec![Some("hello"), Some("world")],
+        })
+    );
+
+    assert_eq!(
+        serde_urlencoded::from_str("list[]=1&list[]=2"),
+        Ok(ListStruct {
+            list: vec![NewType(1), NewType(2)],
+        })
+    );
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Address {
+    city: String,
+    zip: u32,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct User {
+    name: String,
+    address: Address,
+    tags: Vec<String>,
+}
+
+#[test]
+fn deserialize_nested_struct() {
+    assert_eq!(
+        serde_urlencoded::from_str(
+            "item[name]=Ann&item[address][city]=Paris&item[tags][]=a\
+             &item[address][zip]=75001&item[tags][]=b"
+        ),
+        Ok(Wrapper {
+            item: User {
+                name: "Ann".to_owned(),
+                address: Address {
+                    city: "Paris".to_owned(),
+                    zip: 75001,
+                },
+                tags: vec!["a".to_owned(), "b".to_owned()],
+            }
+        })
+    );
+
+    assert_eq!(
+        serde_urlencoded::from_str("item%5Bzip%5D=75001&item%5Bcity%5D=Paris"),
+        Ok(Wrapper {
+            item: Address {
+                city: "Paris".to_owned(),
+                zip: 75001,
+            }
+        })
+    );
+}
+
+#[test]
+fn deserialize_nested_map() {
+    use std::collections::BTreeMap;
+
+    let mut inner = BTreeMap::new();
+    inner.insert(1, "a");
+    inner.insert(2, "b");
+
+    assert_eq!(
+        serde_urlencoded::from_str("item[2]=b&item[1]=a"),
+        Ok(Wrapper { item: inner })
+    );
+
+    let mut inner = BTreeMap::new();
+    inner.insert("y", "z");
+    let mut outer = BTreeMap::new();
+    outer.insert("x", inner);
+
+    assert_eq!(
+        serde_urlencoded::from_str("item[x][y]=z"),
+        Ok(Wrapper { item: outer })
+    );
+}
+
+#[test]
+fn deserialize_flat_bracketed_keys() {
+    use std::collections::HashMap;
+
+    // Keys read as strings keep their brackets, as they always did.
+    let mut map = HashMap::new();
+    map.insert("filter[name]".to_owned(), "x".to_owned());
+    map.insert("page[size]".to_owned(), "10".to_owned());
+    assert_eq!(
+        serde_urlencoded::from_str("filter[name]=x&page[size]=10"),
+        Ok(map)
+    );
+
+    // The pairs of a group stay at the position of its first pair.
+    assert_eq!(
+        serde_urlencoded::from_str("items[0]=a&b=1&items[1][c]=d&items[0]=e"),
+        Ok(vec![
+            ("items[0]".to_owned(), "a".to_owned()),
+            ("items[1][c]".to_owned(), "d".to_owned()),
+            ("items[0]".to_owned(), "e".to_owned()),
+            ("b".to_owned(), "1".to_owned()),
+        ])
+    );
+
+    let err = Vec::<(String, u32)>::deserialize(Deserializer::from_bytes(
+        b"a=1&b[x]=2&b[y]=z",
+    ))
+    .unwrap_err();
+    assert_eq!(err.key(), Some("b[y]"));
+    assert_eq!(err.span(), Some(11..17));
+}
+
+#[test]
+fn deserialize_nested_struct_errors() {
+    assert_matches!(
+        serde_urlencoded::from_str::<Wrapper<Address>>("item[city]=Paris"),
+        Err(error) if error.to_string() == "item: missing field `zip`"
+    );
+
+    assert_matches!(
+        serde_urlencoded::from_str::<Wrapper<Address>>("item=Paris"),
+        Err(error) if error.to_string().contains("invalid type")
+    );
+
+    // Malformed brackets are not split.
+    assert_eq!(
+        serde_urlencoded::from_str("a[b=1&a]b[=2&a[b]c=3"),
+        Ok(vec![("a[b", 1), ("a]b[", 2), ("a[b]c", 3)])
+    );
+
+    // Neither are keys without a root.
+    assert_eq!(
+        serde_urlencoded::from_str("[x]=4&[]=5"),
+        Ok(vec![
+            ("[x]".to_owned(), "4".to_owned()),
+            ("[]".to_owned(), "5".to_owned())
+        ])
+    );
+}
+
+#[test]
+fn deserialize_nested_round_trip() {
+    #[derive(Debug, PartialEq, Deserialize, serde_derive::Serialize)]
+    struct Form {
+        user: Inner,
+        page: u32,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, serde_derive::Serialize)]
+    struct Inner {
+        name: String,
+        address: Option<Coordinates>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, serde_derive::Serialize)]
+    struct Coordinates {
+        lat: f64,
+        lng: f64,
+    }
+
+    let form = Form {
+        user: Inner {
+            name: "Ann Lee".to_owned(),
+            address: Some(Coordinates {
+                lat: 48.85,
+                lng: 2.35,
+            }),
+        },
+        page: 2,
+    };
+
+    let encoded = serde_urlencoded::to_string(&form).unwrap();
+    assert_eq!(serde_urlencoded::from_str(&encoded), Ok(form));
+}
+
+#[test]
+fn deserialize_indexed_list() {
+    assert_eq!(
+        serde_urlencoded::from_str("list[1]=world&list[0]=hello"),
+        Ok(NewStruct {
+            list: vec!["hello", "world"],
+        })
+    );
+
+    assert_eq!(
+        serde_urlencoded::from_str("list[10]=3&list[2]=1&list[5]=2"),
+        Ok(NumList {
+            list: vec![1, 2, 3],
+        })
+    );
+
+    assert_eq!(
+        serde_urlencoded::from_str("item[1]=B&item[0]=A"),
+        Ok(Wrapper { item: (X::A, X::B) })
+    );
+}
+
+#[test]
+fn deserialize_indexed_list_errors() {
+    assert_matches!(
+        serde_urlencoded::from_str::<NumList>("list[0]=1&list[0]=2"),
+        Err(error) if error.to_string() == "list: duplicate index 0"
+    );
+
+    assert_matches!(
+        serde_urlencoded::from_str::<NumList>("list[0]=1&list[x]=2"),
+        Err(error) if error.to_string().contains("unsupported")
+    );
+
+    // Only canonical indices are read as such.
+    for input in &["list[01]=1&list[1]=2", "list[+1]=1", "list[ 1]=1"] {
+        let err = serde_urlencoded::from_str::<NumList>(input).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported, "{}", input);
+    }
+    let mut map = std::collections::HashMap::new();
+    map.insert("01".to_owned(), "x".to_owned());
+    map.insert("1".to_owned(), "y".to_owned());
+    assert_eq!(
+        serde_urlencoded::from_str("item[01]=x&item[1]=y"),
+        Ok(Wrapper { item: map })
+    );
+}
+
+#[test]
+fn deserialize_sequence_gaps() {
+    let input = b"list[2]=3&list[0]=1&list[1]=2";
+    let de =
+        Deserializer::from_bytes(input).sequence_gaps(SequenceGaps::Reject);
+    assert_eq!(
+        NumList::deserialize(de),
+        Ok(NumList {
+            list: vec![1, 2, 3],
+        })
+    );
+
+    let input = b"list[5]=a&list[1]=b";
+    let de =
+        Deserializer::from_bytes(input).sequence_gaps(SequenceGaps::Reject);
+    let err = NumList::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Malformed);
+    assert_eq!(err.path(), Some("list"));
+    assert_eq!(err.to_string(), "list: missing index 0");
+
+    let input = b"0[name]=pear&0[qty]=1&2[name]=fig&2[qty]=3";
+    let de =
+        Deserializer::from_bytes(input).sequence_gaps(SequenceGaps::Reject);
+    let err = Vec::<LineItem>::deserialize(de).unwrap_err();
+    assert_eq!(err.to_string(), "missing index 1");
+}
+
+#[derive(Debug, PartialEq, Deserialize, serde_derive::Serialize)]
+struct LineItem {
+    name: String,
+    qty: u32,
+}
+
+#[test]
+fn deserialize_list_of_structs() {
+    assert_eq!(
+        serde_urlencoded::from_str(
+            "item[1][name]=pear&item[0][qty]=2&item[0][name]=apple\
+             &item[1][qty]=5"
+        ),
+        Ok(Wrapper {
+            item: vec![
+                LineItem {
+                    name: "apple".to_owned(),
+                    qty: 2,
+                },
+                LineItem {
+                    name: "pear".to_owned(),
+                    qty: 5,
+                },
+            ]
+        })
+    );
+
+    assert_eq!(
+        serde_urlencoded::from_str(
+            "1[name]=pear&1[qty]=5&0[name]=apple&0[qty]=2"
+        ),
+        Ok(vec![
+            LineItem {
+                name: "apple".to_owned(),
+                qty: 2,
+            },
+            LineItem {
+                name: "pear".to_owned(),
+                qty: 5,
+            },
+        ])
+    );
+}
+
+#[test]
+fn deserialize_list_of_structs_round_trip() {
+    let items = vec![
+        LineItem {
+            name: "apple".to_owned(),
+            qty: 2,
+        },
+        LineItem {
+            name: "pear".to_owned(),
+            qty: 5,
+        },
+    ];
+
+    let encoded = serde_urlencoded::to_string(&items).unwrap();
+    assert_eq!(serde_urlencoded::from_str(&encoded), Ok(items));
+
+    let wrapper = Wrapper {
+        item: vec![LineItem {
+            name: "fig".to_owned(),
+            qty: 1,
+        }],
+    };
+    let encoded = serde_urlencoded::to_string(&wrapper).unwrap();
+    assert_eq!(serde_urlencoded::from_str(&encoded), Ok(wrapper));
+}
+
+fn from_str_repeated<'de, T>(input: &'de str) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    let parse = form_urlencoded::parse(input.as_bytes());
+    T::deserialize(Deserializer::new(parse).repeated_keys(true))
+}
+
+#[test]
+fn deserialize_repeated_keys() {
+    assert_eq!(
+        from_str_repeated("list=hello&list=world"),
+        Ok(NewStruct {
+            list: vec!["hello", "world"],
+        })
+    );
+
+    assert_eq!(
+        from_str_repeated("list=1&other=x&list=2&list[]=3"),
+        Ok(NumList {
+            list: vec![1, 2, 3],
+        })
+    );
+
+    assert_eq!(from_str_repeated("list=1"), Ok(NumList { list: vec![1] }));
+
+    assert_eq!(
+        from_str_repeated("a=10&b=Hello"),
+        Ok(Params {
+            a: 10,
+            b: "Hello",
+            c: None,
+        })
+    );
+
+    assert_matches!(
+        from_str_repeated::<Params>("a=10&a=11&b=Hello"),
+        Err(error) if error.to_string().contains("unsupported")
+    );
+
+    // Without the option, repeated keys are separate entries.
+    assert_eq!(
+        serde_urlencoded::from_str("list=1&list=2"),
+        Ok(vec![("list", 1), ("list", 2)])
+    );
+}
+
+#[test]
+fn deserialize_delimited_list() {
+    let parse = form_urlencoded::parse(b"list=1,2,3");
+    let de = Deserializer::new(parse).list_delimiter(',');
+    assert_eq!(
+        NumList::deserialize(de),
+        Ok(NumList {
+            list: vec![1, 2, 3],
+        })
+    );
+
+    let parse = form_urlencoded::parse(b"item=hello%7Cworld");
+    let de = Deserializer::new(parse).list_delimiter('|');
+    assert_eq!(
+        Wrapper::<Vec<String>>::deserialize(de),
+        Ok(Wrapper {
+            item: vec!["hello".to_owned(), "world".to_owned()],
+        })
+    );
+
+    let parse = form_urlencoded::parse(b"list=a+b+c&page=10");
+    let de = Deserializer::new(parse).list_delimiter(' ');
+    assert_eq!(
+        Vec::<(String, Vec<String>)>::deserialize(de),
+        Ok(vec![
+            (
+                "list".to_owned(),
+                vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]
+            ),
+            ("page".to_owned(), vec!["10".to_owned()]),
+        ])
+    );
+
+    let parse = form_urlencoded::parse(b"list=");
+    let de = Deserializer::new(parse).list_delimiter(',');
+    assert_eq!(NumList::deserialize(de), Ok(NumList { list: vec![] }));
+
+    let parse = form_urlencoded::parse(b"list=1,x");
+    let de = Deserializer::new(parse).list_delimiter(',');
+    assert_matches!(
+        NumList::deserialize(de),
+        Err(error) if error.to_string() == "list[1]: invalid digit found in string"
+    );
+}
+
+#[test]
+fn deserialize_preserves_order() {
+    assert_eq!(
+        from_str_repeated("b=1&a[]=x&c=2&a[]=y&d[0]=3&e=4&c=5"),
+        Ok(vec![
+            ("b", vec!["1"]),
+            ("a", vec!["x", "y"]),
+            ("c", vec!["2", "5"]),
+            ("d[0]", vec!["3"]),
+            ("e", vec!["4"]),
+        ])
+    );
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<'a> {
+        One(&'a str),
+        Many(Vec<&'a str>),
+    }
+
+    assert_eq!(
+        serde_urlencoded::from_str("b=1&a[]=x&c=2&a[]=y&c=3"),
+        Ok(vec![
+            ("b", OneOrMany::One("1")),
+            ("a", OneOrMany::Many(vec!["x", "y"])),
+            ("c", OneOrMany::One("2")),
+            ("c", OneOrMany::One("3")),
+        ])
+    );
+
+    // Plain pairs buffered after a sequence keep their order however many
+    // there are.
+    let input = (0..1000)
+        .map(|i| format!("k{}={}&a[]={}", i, i, i))
+        .collect::<Vec<_>>()
+        .join("&");
+    let pairs =
+        serde_urlencoded::from_str::<Vec<(String, OneOrMany)>>(&input).unwrap();
+    assert_eq!(pairs.len(), 1001);
+    assert_eq!(pairs[0], ("k0".to_owned(), OneOrMany::One("0")));
+    assert_matches!(pairs[1], (ref key, OneOrMany::Many(ref a)) if key == "a" && a.len() == 1000);
+    for (i, pair) in pairs[2..].iter().enumerate() {
+        assert_eq!(pair.0, format!("k{}", i + 1));
+    }
+}
+
+fn from_str_limited<'de, T>(input: &'de str, limits: Limits) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(Deserializer::from_bytes(input.as_bytes()).limits(limits))
+}
+
+#[test]
+fn deserialize_limits() {
+    let input = "a=1&b=22&c[]=3&c[]=4";
+    let limits = Limits::new()
+        .max_input_bytes(input.len())
+        .max_pairs(4)
+        .max_key_len(4)
+        .max_value_len(2)
+        .max_sequence_len(2);
+    assert!(from_str_limited::<Vec<(&str, &str)>>("a=1&b=22", limits).is_ok());
+    assert!(
+        from_str_limited::<Vec<(&str, Vec<i32>)>>("c[]=3&c[]=4", limits)
+            .is_ok()
+    );
+
+    let exceeded = [
+        ("a=1&b=22&c[]=3&c[]=4&", "input bytes"),
+        ("a&b&c&d&e", "pairs"),
+        ("abcde=1", "key length"),
+        ("a=123", "value length"),
+        ("c[]=3&c[]=4&c[]=5", "sequence length"),
+        ("u[a]=1&u[b]=2&u[c]=3", "sequence length"),
+    ];
+    for &(input, what) in &exceeded {
+        let err =
+            from_str_limited::<Vec<(&str, &str)>>(input, limits).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded, "{}", input);
+        assert!(err.to_string().contains(what), "{}: {}", input, err);
+    }
+
+    // The limit error takes precedence over the visitor's own errors.
+    let err = from_str_limited::<Wrapper<Vec<i32>>>(
+        "item[]=1&item[]=2&item[]=3",
+        limits,
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+
+    // Delimited values are sequences too.
+    let limits = Limits::new().max_sequence_len(2);
+    let de = Deserializer::from_bytes(b"list=1,2")
+        .list_delimiter(',')
+        .limits(limits);
+    assert_eq!(NumList::deserialize(de), Ok(NumList { list: vec![1, 2] }));
+    let de = Deserializer::from_bytes(b"list=1,2,3,4,5")
+        .limits(limits)
+        .list_delimiter(',');
+    let err = NumList::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    assert_eq!(err.key(), Some("list"));
+    assert_eq!(err.span(), Some(0..14));
+
+    let limits = Limits::new().max_depth(1);
+    assert!(from_str_limited::<Wrapper<Vec<i32>>>("item[]=1", limits).is_ok());
+    let err = from_str_limited::<Wrapper<Wrapper<Vec<i32>>>>(
+        "item[item][]=1",
+        limits,
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    assert_eq!(err.key(), Some("item[item][]"));
+}
+
+#[test]
+fn deserialize_key_depth() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Node {
+        a: Option<Box<Node>>,
+        v: Option<String>,
+    }
+
+    // Deeply nested keys are rejected before they are grouped recursively.
+    let input = format!("a{}=1", "[x]".repeat(10_000));
+    let err = serde_urlencoded::from_str::<Vec<(String, String)>>(&input)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    assert_eq!(err.to_string(), "limit exceeded: depth (limit is 32)");
+    assert_eq!(err.span(), Some(0..input.len()));
+
+    let input = format!("a{}[v]=x", "[a]".repeat(31));
+    let mut node = serde_urlencoded::from_str::<Node>(&input).unwrap();
+    for _ in 0..32 {
+        node = *node.a.unwrap();
+    }
+    assert_eq!(node.v.as_deref(), Some("x"));
+
+    let input = format!("a{}[v]=x", "[a]".repeat(32));
+    let err = serde_urlencoded::from_str::<Node>(&input).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+}
+
+#[test]
+fn deserialize_limits_from_reader() {
+    // The reader never ends, the limit stops reading.
+    let reader = std::io::repeat(b'a');
+    let limits = Limits::new().max_input_bytes(1024);
+    let err = serde_urlencoded::de::from_reader_with_limits::<
+        Vec<(String, String)>,
+        _,
+    >(reader, limits)
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    assert!(err.to_string().contains("input bytes"));
+
+    assert_eq!(
+        serde_urlencoded::de::from_reader_with_limits(&b"a=1"[..], limits),
+        Ok(vec![("a".to_owned(), 1)])
+    );
+}
+
+fn from_str_policy<'de, T>(
+    input: &'de str,
+    policy: DuplicateKeys,
+) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    let de = Deserializer::from_bytes(input.as_bytes()).duplicate_keys(policy);
+    T::deserialize(de)
+}
+
+#[test]
+fn deserialize_duplicate_keys() {
+    let input = "a=1&b=2&a=3";
+    assert_eq!(
+        from_str_policy(input, DuplicateKeys::KeepAll),
+        Ok(vec![("a", 1), ("b", 2), ("a", 3)])
+    );
+    assert_eq!(
+        from_str_policy(input, DuplicateKeys::First),
+        Ok(vec![("a", 1), ("b", 2)])
+    );
+    assert_eq!(
+        from_str_policy(input, DuplicateKeys::Last),
+        Ok(vec![("a", 3), ("b", 2)])
+    );
+    assert_eq!(
+        from_str_policy::<Vec<(&str, i32)>>(input, DuplicateKeys::Reject)
+            .unwrap_err()
+            .to_string(),
+        "duplicate key `a`"
+    );
+
+    // The policy applies to structs and to nested keys as well.
+    assert_eq!(
+        from_str_policy("item=1&item=2", DuplicateKeys::Last),
+        Ok(Wrapper { item: 2 })
+    );
+    assert_eq!(
+        from_str_policy(
+            "item[city]=Paris&item[zip]=75001&item[city]=Lyon",
+            DuplicateKeys::First
+        ),
+        Ok(Wrapper {
+            item: Address {
+                city: "Paris".to_owned(),
+                zip: 75001,
+            },
+        })
+    );
+    assert_eq!(
+        from_str_policy::<Wrapper<Address>>(
+            "item[city]=Paris&item[zip]=75001&item[city]=Lyon",
+            DuplicateKeys::Reject
+        )
+        .unwrap_err()
+        .to_string(),
+        "duplicate key `item[city]`"
+    );
+
+    // Sequence elements are not duplicates.
+    assert_eq!(
+        from_str_policy("a[]=1&a[]=2", DuplicateKeys::Reject),
+        Ok(vec![("a", vec![1, 2])])
+    );
+}
+
+#[test]
+fn deserialize_duplicate_keys_of_different_shapes() {
+    assert_eq!(
+        from_str_policy("a=1&a[]=2&a[]=3", DuplicateKeys::First),
+        Ok(vec![("a", 1)])
+    );
+    assert_eq!(
+        from_str_policy("a=1&a[]=2&a[]=3", DuplicateKeys::Last),
+        Ok(vec![("a", vec![2, 3])])
+    );
+    assert_eq!(
+        from_str_policy::<Vec<(&str, Vec<i32>)>>(
+            "a[]=1&a[x]=2",
+            DuplicateKeys::Reject
+        )
+        .unwrap_err()
+        .to_string(),
+        "duplicate key `a[x]`"
+    );
+
+    // Repeated plain keys are sequences, but still conflict with maps.
+    let de = Deserializer::from_bytes(b"a=1&a[]=2&a=3")
+        .repeated_keys(true)
+        .duplicate_keys(DuplicateKeys::Reject);
+    assert_eq!(
+        Vec::<(&str, Vec<i32>)>::deserialize(de),
+        Ok(vec![("a", vec![1, 2, 3])])
+    );
+    let de = Deserializer::from_bytes(b"a=1&a[x]=2")
+        .repeated_keys(true)
+        .duplicate_keys(DuplicateKeys::Reject);
+    assert_eq!(
+        Vec::<(&str, Vec<i32>)>::deserialize(de)
+            .unwrap_err()
+            .to_string(),
+        "duplicate key `a[x]`"
+    );
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+struct Person {
+    name: String,
+    age: u8,
+}
+
+#[test]
+fn deserialize_error_kinds() {
+    let err =
+        serde_urlencoded::from_str::<Person>("name=Ann&age=abc").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+    assert_eq!(err.key(), Some("age"));
+    assert_eq!(err.value(), Some("abc"));
+    assert_eq!(err.to_string(), "age: invalid digit found in string");
+
+    let err = serde_urlencoded::from_str::<Person>("age=30").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::MissingField);
+    assert_eq!(err.key(), Some("name"));
+    assert_eq!(err.value(), None);
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Strict {
+        name: String,
+    }
+
+    let err = serde_urlencoded::from_str::<Strict>("name=Ann&admin=true")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnknownField);
+    assert_eq!(err.key(), Some("admin"));
+
+    let err =
+        serde_urlencoded::from_str::<Person>("name[]=Ann&age=30").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(err.key(), Some("name"));
+    assert_eq!(err.value(), None);
+
+    let err = serde_urlencoded::from_str::<Person>("name=Ann&age=30&age=31")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+    assert_eq!(err.key(), Some("age"));
+
+    let err =
+        serde_urlencoded::from_str::<Wrapper<Vec<i32>>>("item[0]=1&item[0]=2")
+            .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+    assert_eq!(err.to_string(), "item: duplicate index 0");
+    assert_eq!(err.key(), Some("item[0]"));
+
+    let err =
+        serde_urlencoded::from_str::<Wrapper<Vec<i32>>>("item[0]=1&item[00]=2")
+            .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    let err =
+        serde_urlencoded::from_str::<Vec<(String, X)>>("a=Z").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+    assert_eq!(err.key(), Some("a"));
+    assert_eq!(err.value(), Some("Z"));
+}
+
+#[test]
+fn deserialize_io_error() {
+    struct Failing;
+
+    impl std::io::Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("broken"))
+        }
+    }
+
+    let err =
+        serde_urlencoded::from_reader::<Vec<(String, String)>, _>(Failing)
+            .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io);
+    assert_eq!(err.to_string(), "could not read input: broken");
+}
+
+#[test]
+fn deserialize_error_span() {
+    let input = "name=Ann&&address[city]=Paris&address[zip]=75%30x1";
+    let err = User::deserialize(Deserializer::from_bytes(input.as_bytes()))
+        .unwrap_err();
+    assert_eq!(err.span(), Some(30..50));
+    assert_eq!(&input[err.span().unwrap()], "address[zip]=75%30x1");
+    assert_eq!(err.value(), Some("750x1"));
+
+    let input = "a=1&b=2&b=3";
+    let de = Deserializer::from_bytes(input.as_bytes())
+        .duplicate_keys(DuplicateKeys::Reject);
+    let err = Vec::<(&str, &str)>::deserialize(de).unwrap_err();
+    assert_eq!(err.span(), Some(8..11));
+
+    let de = Deserializer::from_bytes(b"a=1&bb=2")
+        .limits(Limits::new().max_key_len(1));
+    let err = Vec::<(&str, &str)>::deserialize(de).unwrap_err();
+    assert_eq!(err.span(), Some(4..8));
+
+    // Errors that are not about a single pair have no span, and neither do
+    // the errors of a deserializer without access to the input.
+    let err =
+        User::deserialize(Deserializer::from_bytes(b"name=Ann")).unwrap_err();
+    assert_eq!(err.span(), None);
+    let de = Deserializer::new(form_urlencoded::parse(b"a=x"));
+    let err = Vec::<(&str, u32)>::deserialize(de).unwrap_err();
+    assert_eq!(err.span(), None);
+}
+
+#[test]
+fn deserialize_error_path() {
+    let err =
+        serde_urlencoded::from_str::<Wrapper<Vec<u32>>>("item[]=1&item[]=x")
+            .unwrap_err();
+    assert_eq!(err.path(), Some("item[1]"));
+    assert_eq!(err.to_string(), "item[1]: invalid digit found in string");
+
+    let err = serde_urlencoded::from_str::<User>(
+        "name=Ann&address[city]=Paris&address[zip]=x&tags[]=a",
+    )
+    .unwrap_err();
+    assert_eq!(err.path(), Some("address.zip"));
+    assert_eq!(err.key(), Some("address[zip]"));
+    assert_eq!(err.value(), Some("x"));
+
+    let err = serde_urlencoded::from_str::<Vec<LineItem>>(
+        "0[name]=apple&0[qty]=2&1[name]=pear&1[qty]=many",
+    )
+    .unwrap_err();
+    assert_eq!(err.path(), Some("[1].qty"));
+
+    let err = serde_urlencoded::from_str::<Wrapper<Vec<LineItem>>>(
+        "item[3][name]=apple&item[3][qty]=x",
+    )
+    .unwrap_err();
+    assert_eq!(err.path(), Some("item[3].qty"));
+
+    // Errors about the top-level struct itself have no path.
+    let err = serde_urlencoded::from_str::<User>("name=Ann").unwrap_err();
+    assert_eq!(err.path(), None);
+}
+
+#[test]
+fn deserialize_collecting_errors() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Form {
+        name: String,
+        age: u8,
+        address: Address,
+        scores: Vec<u32>,
+    }
+
+    let input = "name=Ann&age=old&address[city]=Paris&address[zip]=x\
+                 &scores[]=1&scores[]=y&scores[]=3";
+    let errors = Deserializer::from_bytes(input.as_bytes())
+        .deserialize_collecting_errors::<Form>()
+        .unwrap_err();
+    let failures = errors
+        .iter()
+        .map(|e| (e.kind(), e.key().unwrap(), e.value().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        failures,
+        [
+            (ErrorKind::Parse, "age", "old"),
+            (ErrorKind::Parse, "address[zip]", "x"),
+            (ErrorKind::Parse, "scores[]", "y"),
+        ]
+    );
+    assert_eq!(errors[1].span(), Some(37..51));
+    assert_eq!(errors[1].path(), None);
+
+    // The error that stops deserialization comes last.
+    let errors = Deserializer::from_bytes(b"age=old&scores[]=1")
+        .deserialize_collecting_errors::<Form>()
+        .unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].key(), Some("age"));
+    assert_eq!(errors[1].kind(), ErrorKind::MissingField);
+
+    // The placeholder of a failed value is accepted by validating types, so
+    // deserialization goes on to the next pair.
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Checked {
+        n: std::num::NonZeroU32,
+        c: char,
+    }
+
+    let errors = Deserializer::from_bytes(b"n=x&c=ab")
+        .deserialize_collecting_errors::<Checked>()
+        .unwrap_err();
+    let failures = errors
+        .iter()
+        .map(|e| (e.kind(), e.key().unwrap(), e.span().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        failures,
+        [(ErrorKind::Parse, "n", 0..3), (ErrorKind::Parse, "c", 4..8)]
+    );
+
+    // A placeholder that is rejected ends the run without repeating the
+    // error of its pair.
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Even {
+        n: Pair,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(try_from = "u32")]
+    struct Pair;
+
+    impl std::convert::TryFrom<u32> for Pair {
+        type Error = String;
+
+        fn try_from(n: u32) -> Result<Self, String> {
+            if n == 2 {
+                Ok(Pair)
+            } else {
+                Err(format!("{} is not two", n))
+            }
+        }
+    }
+
+    let errors = Deserializer::from_bytes(b"n=x")
+        .deserialize_collecting_errors::<Even>()
+        .unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind(), ErrorKind::Parse);
+    assert_eq!(errors[0].key(), Some("n"));
+
+    let input =
+        "name=Ann&age=30&address[city]=Paris&address[zip]=75001&scores[]=7";
+    assert_eq!(
+        Deserializer::from_bytes(input.as_bytes())
+            .deserialize_collecting_errors(),
+        Ok(Form {
+            name: "Ann".to_owned(),
+            age: 30,
+            address: Address {
+                city: "Paris".to_owned(),
+                zip: 75001,
+            },
+            scores: vec![7],
+        })
+    );
+}
+
+#[test]
+fn deserialize_charset() {
+    type Pairs = Vec<(String, String)>;
+    let latin1 = Charset::for_label("latin1").unwrap();
+
+    let de = Deserializer::from_bytes(b"caf%E9=cr%E8me+br%FBl%E9e&euro=%80")
+        .charset(latin1);
+    assert_eq!(
+        Pairs::deserialize(de),
+        Ok(vec![
+            ("café".to_owned(), "crème brûlée".to_owned()),
+            ("euro".to_owned(), "€".to_owned()),
+        ])
+    );
+
+    // The `_charset_` pair wins over the configured charset, wherever it is.
+    let de = Deserializer::from_bytes(b"name=%83%5E&_charset_=sjis")
+        .charset(latin1)
+        .use_charset_field(true);
+    assert_eq!(
+        Pairs::deserialize(de),
+        Ok(vec![
+            ("name".to_owned(), "タ".to_owned()),
+            ("_charset_".to_owned(), "sjis".to_owned()),
+        ])
+    );
+
+    // An unknown charset falls back to the configured one.
+    let de = Deserializer::from_bytes(b"_charset_=klingon&name=%E9")
+        .charset(latin1)
+        .use_charset_field(true);
+    assert_eq!(
+        Pairs::deserialize(de).unwrap()[1],
+        ("name".to_owned(), "é".to_owned())
+    );
+
+    // Without the option, `_charset_` is an ordinary pair.
+    let de = Deserializer::from_bytes(b"_charset_=sjis&name=%C3%A9");
+    assert_eq!(
+        Pairs::deserialize(de).unwrap()[1],
+        ("name".to_owned(), "é".to_owned())
+    );
+
+    // `form_urlencoded::Parse` only decodes UTF-8.
+    let parse = form_urlencoded::parse(b"name=%C3%A9");
+    let de = Deserializer::new(parse).charset(latin1);
+    let err = Pairs::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        err.to_string(),
+        "`charset` requires a deserializer created with `from_bytes`"
+    );
+    let parse = form_urlencoded::parse(b"name=%C3%A9");
+    let de = Deserializer::new(parse).use_charset_field(true);
+    assert_eq!(
+        Pairs::deserialize(de).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    let parse = form_urlencoded::parse(b"name=%C3%A9");
+    let de = Deserializer::new(parse).charset(Charset::default());
+    assert_eq!(
+        Pairs::deserialize(de),
+        Ok(vec![("name".to_owned(), "é".to_owned())])
+    );
+}
+
+#[test]
+fn deserialize_strict_utf8() {
+    type Pairs = Vec<(String, String)>;
+
+    let input = b"a=ok&b=caf%E9+noir&c=3";
+    assert_eq!(
+        Pairs::deserialize(Deserializer::from_bytes(input)).unwrap()[1].1,
+        "caf\u{FFFD} noir"
+    );
+
+    let de = Deserializer::from_bytes(input).strict_utf8(true);
+    let err = Pairs::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Utf8);
+    assert_eq!(err.key(), Some("b"));
+    assert_eq!(err.value(), Some("caf\u{FFFD} noir"));
+    assert_eq!(err.span(), Some(5..18));
+    let source = std::error::Error::source(&err).unwrap();
+    let utf8 = source.downcast_ref::<std::str::Utf8Error>().unwrap();
+    assert_eq!(utf8.valid_up_to(), 3);
+
+    let de = Deserializer::from_bytes(b"a=1&%FFid=2").strict_utf8(true);
+    let err = Pairs::deserialize(de).unwrap_err();
+    assert_eq!(err.key(), Some("\u{FFFD}id"));
+    assert_eq!(
+        err.to_string(),
+        "invalid UTF-8 in key: invalid utf-8 sequence of 1 bytes from index 0"
+    );
+
+    let de =
+        Deserializer::from_bytes(b"name=caf%C3%A9+au+lait").strict_utf8(true);
+    assert_eq!(
+        Pairs::deserialize(de),
+        Ok(vec![("name".to_owned(), "café au lait".to_owned())])
+    );
+
+    let parse = form_urlencoded::parse(b"name=caf%C3%A9");
+    let err = Pairs::deserialize(Deserializer::new(parse).strict_utf8(true))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        err.to_string(),
+        "`strict_utf8` requires a deserializer created with `from_bytes`"
+    );
+
+    let parse = form_urlencoded::parse(b"name=caf%C3%A9");
+    assert_eq!(
+        Pairs::deserialize(Deserializer::new(parse).strict_utf8(false)),
+        Ok(vec![("name".to_owned(), "café".to_owned())])
+    );
+
+    let parse = form_urlencoded::parse(b"name=caf%C3%A9");
+    let de = Deserializer::new(parse)
+        .strict_utf8(true)
+        .strict_utf8(false);
+    assert_eq!(
+        Pairs::deserialize(de),
+        Ok(vec![("name".to_owned(), "café".to_owned())])
+    );
+}
+
+#[test]
+fn deserialize_raw_bytes() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Token<'a> {
+        #[serde(with = "serde_bytes")]
+        id: Vec<u8>,
+        #[serde(borrow, with = "serde_bytes")]
+        name: &'a [u8],
+        #[serde(with = "serde_bytes")]
+        text: Vec<u8>,
+    }
+
+    assert_eq!(
+        serde_urlencoded::from_str("id=%00%FF%C3a+b&name=abc&text=caf%C3%A9"),
+        Ok(Token {
+            id: vec![0x00, 0xFF, 0xC3, b'a', b' ', b'b'],
+            name: b"abc",
+            text: "café".as_bytes().to_vec(),
+        })
+    );
+
+    // Legacy-encoded values are left as they are, whatever the charset.
+    let de = Deserializer::from_bytes(b"id=%E9&name=n&text=caf%E9")
+        .charset(Charset::for_label("latin1").unwrap());
+    assert_eq!(
+        Token::deserialize(de).unwrap().text,
+        vec![b'c', b'a', b'f', 0xE9]
+    );
+}
+
+#[test]
+fn deserialize_strict_encoding() {
+    type Pairs = Vec<(String, String)>;
+    let strict = |input: &'static [u8]| {
+        Pairs::deserialize(
+            Deserializer::from_bytes(input).strict_encoding(true),
+        )
+    };
+
+    assert_eq!(
+        Pairs::deserialize(Deserializer::from_bytes(b"a=%zz&b=100%")),
+        Ok(vec![
+            ("a".to_owned(), "%zz".to_owned()),
+            ("b".to_owned(), "100%".to_owned()),
+        ])
+    );
+    assert_eq!(
+        strict(b"a=%41%2b+c&b=%e9%C3%A9"),
+        Ok(vec![
+            ("a".to_owned(), "A+ c".to_owned()),
+            ("b".to_owned(), "\u{FFFD}é".to_owned()),
+        ])
+    );
+    assert_eq!(
+        strict(b"note=one%0D%0Atwo%09three%0a"),
+        Ok(vec![("note".to_owned(), "one\r\ntwo\tthree\n".to_owned())])
+    );
+
+    let errors = [
+        (
+            &b"a=%zz&b=1"[..],
+            "a",
+            0..5,
+            "malformed percent-escape `%zz` at byte 2",
+        ),
+        (
+            b"a=1&b=100%",
+            "b",
+            4..10,
+            "malformed percent-escape `%` at byte 9",
+        ),
+        (
+            b"a=1&b=%4",
+            "b",
+            4..8,
+            "malformed percent-escape `%4` at byte 6",
+        ),
+        (
+            b"a%2=1",
+            "a%2",
+            0..5,
+            "malformed percent-escape `%2=` at byte 1",
+        ),
+        (
+            b"a=caf\xC3\xA9",
+            "a",
+            0..7,
+            "raw non-ASCII byte 0xC3 at byte 5",
+        ),
+        (b"a=1\n", "a", 0..4, "control character 0x0A at byte 3"),
+        (
+            b"a=1&b=x%00y",
+            "b",
+            4..11,
+            "percent-encoded control character 0x00 at byte 7",
+        ),
+        (b"a=1\t", "a", 0..4, "control character 0x09 at byte 3"),
+        (
+            b"a=1&b=x%1By",
+            "b",
+            4..11,
+            "percent-encoded control character 0x1B at byte 7",
+        ),
+        (
+            b"%7F=1",
+            "\u{7F}",
+            0..5,
+            "percent-encoded control character 0x7F at byte 0",
+        ),
+    ];
+    for (input, key, span, message) in errors.iter().cloned() {
+        let err = strict(input).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Malformed);
+        assert_eq!(err.key(), Some(key));
+        assert_eq!(err.span(), Some(span));
+        assert_eq!(err.to_string(), message);
+    }
+
+    let parse = form_urlencoded::parse(b"a=%zz");
+    let de = Deserializer::new(parse).strict_encoding(true);
+    let err = Pairs::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        err.to_string(),
+        "`strict_encoding` requires a deserializer created with `from_bytes`"
+    );
+
+    let parse = form_urlencoded::parse(b"a=%zz");
+    let de = Deserializer::new(parse)
+        .strict_encoding(true)
+        .strict_encoding(false);
+    assert_eq!(Pairs::deserialize(de), Ok(vec![("a".into(), "%zz".into())]));
+}
+
+#[test]
+fn deserialize_spaces() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Query {
+        q: String,
+        #[serde(with = "serde_bytes")]
+        raw: Vec<u8>,
+    }
+
+    let input = b"q=a+b%20c%2Bd&raw=1+2";
+    assert_eq!(
+        serde_urlencoded::from_bytes(input),
+        Ok(Query {
+            q: "a b c+d".to_owned(),
+            raw: b"1 2".to_vec(),
+        })
+    );
+
+    let percent = SpaceEncoding::Percent;
+    assert_eq!(
+        Query::deserialize(Deserializer::from_bytes(input).spaces(percent)),
+        Ok(Query {
+            q: "a+b c+d".to_owned(),
+            raw: b"1+2".to_vec(),
+        })
+    );
+
+    // Every decoding mode agrees on what `+` is.
+    let de = Deserializer::from_bytes(b"a+b=c+%E9")
+        .spaces(percent)
+        .strict_utf8(true);
+    assert_matches!(
+        Vec::<(String, String)>::deserialize(de),
+        Err(ref err) if err.key() == Some("a+b") && err.value() == Some("c+\u{FFFD}")
+    );
+    let de = Deserializer::from_bytes(b"a+b=c+%E9")
+        .spaces(percent)
+        .charset(Charset::for_label("latin1").unwrap());
+    assert_eq!(
+        Vec::<(String, String)>::deserialize(de),
+        Ok(vec![("a+b".to_owned(), "c+é".to_owned())])
+    );
+
+    let parse = form_urlencoded::parse(b"q=a+b");
+    let err = Query::deserialize(Deserializer::new(parse).spaces(percent))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        err.to_string(),
+        "`spaces` requires a deserializer created with `from_bytes`"
+    );
+}
+
+#[test]
+fn deserialize_inferred_types() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Range {
+        min: f64,
+        max: i32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Pagination {
+        page: u32,
+        sorted: bool,
+        range: Range,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u64),
+        Name(String),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Search {
+        id: Id,
+        #[serde(flatten)]
+        pagination: Pagination,
+        #[serde(flatten)]
+        extra: std::collections::BTreeMap<String, String>,
+    }
+
+    let input = b"id=42&page=2&sorted=true&range[min]=-0.5&range[max]=-3&q=007";
+    assert_eq!(
+        Search::deserialize(Deserializer::from_bytes(input).infer_types(true)),
+        Ok(Search {
+            id: Id::Number(42),
+            pagination: Pagination {
+                page: 2,
+                sorted: true,
+                range: Range { min: -0.5, max: -3 },
+            },
+            extra: vec![("q".to_owned(), "007".to_owned())]
+                .into_iter()
+                .collect(),
+        })
+    );
+    assert_matches!(
+        Search::deserialize(Deserializer::from_bytes(input)),
+        Err(ref err) if err.to_string().contains("invalid type: string \"2\"")
+    );
+
+    let input = b"id=x&page=1&sorted=false&range[min]=1.0&range[max]=0";
+    let search =
+        Search::deserialize(Deserializer::from_bytes(input).infer_types(true));
+    assert_eq!(search.unwrap().id, Id::Name("x".to_owned()));
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Request {
+        Create { name: String, count: u32 },
+        Delete { id: u64, force: bool },
+    }
+
+    let de = Deserializer::from_bytes(b"type=delete&id=7&force=true")
+        .infer_types(true);
+    assert_eq!(
+        Request::deserialize(de),
+        Ok(Request::Delete { id: 7, force: true })
+    );
+    let de = Deserializer::from_bytes(b"name=bolt&count=12&type=create")
+        .infer_types(true);
+    assert_eq!(
+        Request::deserialize(de),
+        Ok(Request::Create {
+            name: "bolt".to_owned(),
+            count: 12,
+        })
+    );
+
+    // Values deserialized with a known type are never inferred.
+    let de = Deserializer::from_bytes(b"a=1&b=true").infer_types(true);
+    assert_eq!(
+        Vec::<(String, String)>::deserialize(de),
+        Ok(vec![
+            ("a".to_owned(), "1".to_owned()),
+            ("b".to_owned(), "true".to_owned()),
+        ])
+    );
+}
+
+#[test]
+fn deserialize_data_enum() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Point(i32, i32),
+        Rect { width: u32, height: u32 },
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        name: String,
+        shape: Shape,
+        border: Option<Shape>,
+        layers: Vec<Shape>,
+    }
+
+    let input = "name=logo&shape[rect][width]=4&shape[rect][height]=2\
+                 &border[circle]=1&layers[0]=empty&layers[1][circle]=3\
+                 &layers[2][point][0]=1&layers[2][point][1]=-2";
+    assert_eq!(
+        serde_urlencoded::from_str(input),
+        Ok(Drawing {
+            name: "logo".to_owned(),
+            shape: Shape::Rect {
+                width: 4,
+                height: 2,
+            },
+            border: Some(Shape::Circle(1)),
+            layers: vec![Shape::Empty, Shape::Circle(3), Shape::Point(1, -2)],
+        })
+    );
+
+    let err = serde_urlencoded::from_str::<Drawing>(
+        "name=logo&shape[circle]=1&shape[empty]=",
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(err.path(), Some("shape"));
+
+    let err = serde_urlencoded::from_str::<Drawing>(
+        "name=logo&shape[rect][width]=4&shape[rect][height]=x",
+    )
+    .unwrap_err();
+    assert_eq!(err.path(), Some("shape.rect.height"));
+}
+
+#[test]
+fn deserialize_top_level_enum() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Push {
+        branch: String,
+        commits: u32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Event {
+        Ping,
+        Push(Push),
+        Star { repo: String },
+    }
+
+    assert_eq!(
+        serde_urlencoded::from_str("event=push&branch=main&commits=2"),
+        Ok(Event::Push(Push {
+            branch: "main".to_owned(),
+            commits: 2,
+        }))
+    );
+    assert_eq!(
+        serde_urlencoded::from_str("repo=serde&event=star"),
+        Ok(Event::Star {
+            repo: "serde".to_owned()
+        })
+    );
+    assert_eq!(serde_urlencoded::from_str("event=ping"), Ok(Event::Ping));
+
+    let err = serde_urlencoded::from_str::<Event>("repo=serde").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::MissingField);
+    assert_eq!(err.key(), Some("event"));
+
+    let err = serde_urlencoded::from_str::<Event>("event=fork").unwrap_err();
+    assert_eq!(err.key(), Some("event"));
+    assert_eq!(err.value(), Some("fork"));
+
+    let err = serde_urlencoded::from_str::<Event>(
+        "event=push&branch=main&commits=many",
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+    assert_eq!(err.path(), Some("commits"));
+
+    let de = Deserializer::from_bytes(b"kind=ping").enum_tag("kind");
+    assert_eq!(Event::deserialize(de), Ok(Event::Ping));
+
+    // Like the unknown fields of a struct, other pairs are ignored.
+    assert_eq!(
+        serde_urlencoded::from_str("event=ping&zen=1&hook[id]=2"),
+        Ok(Event::Ping)
+    );
+
+    let de = Deserializer::from_bytes(b"event=push&event=ping");
+    let err = Event::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+    assert_eq!(err.key(), Some("event"));
+    assert_eq!(err.span(), Some(11..21));
+    assert_eq!(err.to_string(), "duplicate key `event`");
+
+    let parse = form_urlencoded::parse(b"event=ping&event=ping");
+    let de = Deserializer::new(parse).repeated_keys(true);
+    let err = Event::deserialize(de).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+
+    let de = Deserializer::from_bytes(b"event=push&event=ping")
+        .duplicate_keys(DuplicateKeys::Last);
+    assert_eq!(Event::deserialize(de), Ok(Event::Ping));
+}
+
+#[test]
+fn deserialize_variant_matching() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Status {
+        Open,
+        InProgress,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Filter {
+        status: Status,
+        statuses: Vec<Status>,
+    }
+
+    let input = b"status=OPEN&statuses[]=In_Progress&statuses[]=open";
+    let de = Deserializer::from_bytes(input)
+        .variant_matching(VariantMatching::IgnoreCase);
+    assert_eq!(
+        Filter::deserialize(de),
+        Ok(Filter {
+            status: Status::Open,
+            statuses: vec![Status::InProgress, Status::Open],
+        })
+    );
+
+    let input = b"status=in-progress&statuses[]=InProgress";
+    let de = Deserializer::from_bytes(input)
+        .variant_matching(VariantMatching::IgnoreCase);
+    let err = Filter::deserialize(de).unwrap_err();
+    assert_eq!(err.key(), Some("status"));
+    assert_eq!(err.value(), Some("in-progress"));
+
+    let de = Deserializer::from_bytes(input)
+        .variant_matching(VariantMatching::IgnoreCaseAndSeparators);
+    assert_eq!(
+        Filter::deserialize(de),
+        Ok(Filter {
+            status: Status::InProgress,
+            statuses: vec![Status::InProgress],
+        })
+    );
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "kebab-case")]
+    enum Event {
+        PullRequest { number: u32 },
+    }
+
+    let de = Deserializer::from_bytes(b"event=PULL_REQUEST&number=7")
+        .variant_matching(VariantMatching::IgnoreCaseAndSeparators);
+    assert_eq!(Event::deserialize(de), Ok(Event::PullRequest { number: 7 }));
+
+    let de = Deserializer::from_bytes(b"status=Open&statuses[]=open");
+    assert!(Filter::deserialize(de).is_err());
+}
+
+#[test]
+fn deserialize_variant_indices() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Status {
+        Draft,
+        Published,
+        #[serde(rename = "7")]
+        Archived,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Post {
+        status: Status,
+        history: Vec<Status>,
+    }
+
+    let input = b"status=7&history[]=0&history[]=Published&history[]=2";
+    let de = Deserializer::from_bytes(input).variant_indices(true);
+    assert_eq!(
+        Post::deserialize(de),
+        Ok(Post {
+            status: Status::Archived,
+            history: vec![Status::Draft, Status::Published, Status::Archived],
+        })
+    );
+
+    let de =
+        Deserializer::from_bytes(b"status=3&history[]=0").variant_indices(true);
+    let err = Post::deserialize(de).unwrap_err();
+    assert_eq!(err.key(), Some("status"));
+    assert_eq!(err.value(), Some("3"));
+
+    let de = Deserializer::from_bytes(b"status=1&history[]=0");
+    assert!(Post::deserialize(de).is_err());
+}
@@ -1,6 +1,7 @@
 use matches::assert_matches;
 use serde_derive::Serialize;
-use serde_urlencoded::ser::Error;
+use serde_urlencoded::ser::{ArrayStyle, Error, ErrorKind, Serializer};
+use serde_urlencoded::{Charset, SpaceEncoding};
 
 #[derive(Serialize)]
 struct NewType<T>(T);
@@ -124,7 +125,7 @@ fn serialize_nested_list() {
     let params = &[("list", vec![vec![0u8]])];
     assert_matches!(
         serde_urlencoded::to_string(params),
-        Err(Error::Custom(s)) if s.contains("unsupported")
+        Err(err) if err.kind() == ErrorKind::Unsupported
     )
 }
 
@@ -165,3 +166,521 @@ fn serialize_map() {
     let encoded = serde_urlencoded::to_string(s).unwrap();
     assert_eq!("hello=world&matrix=ruma&seri=alize", encoded);
 }
+
+#[derive(Serialize)]
+struct Address {
+    city: String,
+    zip: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct User {
+    name: String,
+    address: Address,
+    tags: Vec<&'static str>,
+}
+
+#[test]
+fn serialize_nested_struct() {
+    let user = User {
+        name: "Ann".to_owned(),
+        address: Address {
+            city: "Paris".to_owned(),
+            zip: Some(75001),
+        },
+        tags: vec!["a", "b"],
+    };
+
+    assert_eq!(
+        serde_urlencoded::to_string(&[("user", user)]),
+        Ok("user%5Bname%5D=Ann\
+            &user%5Baddress%5D%5Bcity%5D=Paris\
+            &user%5Baddress%5D%5Bzip%5D=75001\
+            &user%5Btags%5D%5B%5D=a&user%5Btags%5D%5B%5D=b"
+            .to_owned())
+    );
+}
+
+#[test]
+fn serialize_nested_map() {
+    let mut inner = std::collections::BTreeMap::new();
+    inner.insert(1, "a");
+    inner.insert(2, "b");
+    let mut outer = std::collections::BTreeMap::new();
+    outer.insert("item", inner);
+
+    assert_eq!(
+        serde_urlencoded::to_string(outer),
+        Ok("item%5B1%5D=a&item%5B2%5D=b".to_owned())
+    );
+
+    for key in &["a]b", "a[b", "[]"] {
+        let mut inner = std::collections::BTreeMap::new();
+        inner.insert(*key, "1");
+        let err = serde_urlencoded::to_string([("x", inner)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert_eq!(err.path(), Some("x"));
+    }
+}
+
+#[derive(Serialize)]
+struct LineItem {
+    name: &'static str,
+    qty: u32,
+}
+
+#[test]
+fn serialize_list_of_structs() {
+    let items = vec![
+        LineItem {
+            name: "apple",
+            qty: 2,
+        },
+        LineItem {
+            name: "pear",
+            qty: 5,
+        },
+    ];
+
+    assert_eq!(
+        serde_urlencoded::to_string([("item", &items)]),
+        Ok("item%5B0%5D%5Bname%5D=apple&item%5B0%5D%5Bqty%5D=2\
+            &item%5B1%5D%5Bname%5D=pear&item%5B1%5D%5Bqty%5D=5"
+            .to_owned())
+    );
+
+    assert_eq!(
+        serde_urlencoded::to_string(&items),
+        Ok(
+            "0%5Bname%5D=apple&0%5Bqty%5D=2&1%5Bname%5D=pear&1%5Bqty%5D=5"
+                .to_owned()
+        )
+    );
+}
+
+fn to_string_with_style<T: serde::Serialize>(
+    input: T,
+    style: ArrayStyle,
+) -> Result<String, Error> {
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    input.serialize(Serializer::new(&mut urlencoder).array_style(style))?;
+    Ok(urlencoder.finish())
+}
+
+#[test]
+fn serialize_array_styles() {
+    #[derive(Serialize)]
+    struct Filter {
+        tag: Vec<&'static str>,
+        ids: Vec<Option<u32>>,
+        page: u32,
+    }
+
+    let filter = Filter {
+        tag: vec!["a", "b c"],
+        ids: vec![Some(1), None, Some(3)],
+        page: 2,
+    };
+
+    assert_eq!(
+        to_string_with_style(&filter, ArrayStyle::Brackets),
+        Ok("tag%5B%5D=a&tag%5B%5D=b+c&ids%5B%5D=1&ids%5B%5D=3&page=2"
+            .to_owned())
+    );
+    assert_eq!(
+        to_string_with_style(&filter, ArrayStyle::Repeat),
+        Ok("tag=a&tag=b+c&ids=1&ids=3&page=2".to_owned())
+    );
+    assert_eq!(
+        to_string_with_style(&filter, ArrayStyle::Indexed),
+        Ok(
+            "tag%5B0%5D=a&tag%5B1%5D=b+c&ids%5B0%5D=1&ids%5B2%5D=3&page=2"
+                .to_owned()
+        )
+    );
+    assert_eq!(
+        to_string_with_style(&filter, ArrayStyle::Delimited(',')),
+        Ok("tag=a%2Cb+c&ids=1%2C3&page=2".to_owned())
+    );
+    assert_eq!(
+        to_string_with_style(&filter, ArrayStyle::Delimited('|')),
+        Ok("tag=a%7Cb+c&ids=1%7C3&page=2".to_owned())
+    );
+    assert_matches!(
+        to_string_with_style(&filter, ArrayStyle::Delimited(' ')),
+        Err(ref err) if err.path() == Some("tag[1]")
+    );
+
+    let params = &[("t", vec!["a,b", "c"])];
+    let err =
+        to_string_with_style(params, ArrayStyle::Delimited(',')).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(err.path(), Some("t[0]"));
+}
+
+#[test]
+fn serialize_array_styles_of_structs() {
+    let items = &[(
+        "item",
+        vec![LineItem {
+            name: "apple",
+            qty: 2,
+        }],
+    )];
+
+    assert_eq!(
+        to_string_with_style(items, ArrayStyle::Repeat),
+        Ok("item%5B0%5D%5Bname%5D=apple&item%5B0%5D%5Bqty%5D=2".to_owned())
+    );
+    assert_eq!(
+        to_string_with_style(items, ArrayStyle::Delimited(',')),
+        Ok("item%5B0%5D%5Bname%5D=apple&item%5B0%5D%5Bqty%5D=2".to_owned())
+    );
+}
+
+/// Serializes as the inner number, or fails if there is none.
+struct Checked(Option<u32>);
+
+impl serde::Serialize for Checked {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self.0 {
+            Some(value) => serializer.serialize_u32(value),
+            None => Err(serde::ser::Error::custom("broken")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Zip {
+    zip: Checked,
+}
+
+#[derive(Serialize)]
+struct Location {
+    name: &'static str,
+    address: Zip,
+}
+
+#[test]
+fn serialize_error_path() {
+    let items = &[("items", vec![Checked(Some(1)), Checked(None)])];
+    assert_eq!(
+        serde_urlencoded::to_string(items).map_err(|e| e.to_string()),
+        Err("items[1]: broken".to_owned())
+    );
+
+    let location = Location {
+        name: "home",
+        address: Zip { zip: Checked(None) },
+    };
+    assert_eq!(
+        serde_urlencoded::to_string(&location).map_err(|e| e.to_string()),
+        Err("address.zip: broken".to_owned())
+    );
+    assert_eq!(
+        serde_urlencoded::to_string(&[("location", location)])
+            .map_err(|e| e.to_string()),
+        Err("location.address.zip: broken".to_owned())
+    );
+
+    let zips = vec![
+        Zip {
+            zip: Checked(Some(1)),
+        },
+        Zip { zip: Checked(None) },
+    ];
+    assert_eq!(
+        serde_urlencoded::to_string(&zips).map_err(|e| e.to_string()),
+        Err("[1].zip: broken".to_owned())
+    );
+
+    assert_matches!(
+        serde_urlencoded::to_string(&[("list", vec![vec![0u8]])]),
+        Err(err) if err.to_string() == "list[0]: unsupported value"
+    );
+}
+
+#[test]
+fn serialize_error_kinds() {
+    assert_matches!(
+        serde_urlencoded::to_string(42),
+        Err(err) if err.kind() == ErrorKind::TopLevel
+    );
+    assert_matches!(
+        serde_urlencoded::to_string(&[(vec![1], 2)]),
+        Err(err) if err.kind() == ErrorKind::Unsupported
+    );
+    assert_matches!(
+        serde_urlencoded::to_string([("a", 1, 2)]),
+        Err(err) if err.kind() == ErrorKind::Unsupported
+    );
+
+    // Deserialization reports through the same error type.
+    let err: Error =
+        serde_urlencoded::from_str::<Vec<(u8, u8)>>("a=1").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+}
+
+#[test]
+fn serialize_charset() {
+    let params = &[("café", "crème brûlée €"), ("name", "日本")];
+
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    let charset = Charset::for_label("windows-1252").unwrap();
+    serde::Serialize::serialize(
+        params,
+        Serializer::new(&mut urlencoder).charset(charset),
+    )
+    .unwrap();
+    assert_eq!(
+        urlencoder.finish(),
+        "caf%E9=cr%E8me+br%FBl%E9e+%80\
+         &name=%26%2326085%3B%26%2326412%3B"
+    );
+
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    let charset = Charset::for_label("UTF-16LE").unwrap();
+    assert_eq!(charset, Charset::default());
+    serde::Serialize::serialize(
+        params,
+        Serializer::new(&mut urlencoder).charset(charset),
+    )
+    .unwrap();
+    assert_eq!(
+        urlencoder.finish(),
+        serde_urlencoded::to_string(params).unwrap()
+    );
+}
+
+#[test]
+fn serialize_raw_bytes() {
+    #[derive(Serialize)]
+    struct Token<'a> {
+        #[serde(with = "serde_bytes")]
+        id: &'a [u8],
+        tags: Vec<serde_bytes::ByteBuf>,
+        text: &'a str,
+    }
+
+    let token = Token {
+        id: &[0x00, 0xFF, 0xC3, b'a', b' ', b'b'],
+        tags: vec![serde_bytes::ByteBuf::from(vec![0xE9])],
+        text: "café",
+    };
+    let encoded = serde_urlencoded::to_string(&token).unwrap();
+    assert_eq!(encoded, "id=%00%FF%C3a+b&tags%5B%5D=%E9&text=caf%C3%A9");
+
+    // Bytes are not transcoded into the charset, unlike text.
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    let charset = Charset::for_label("latin1").unwrap();
+    serde::Serialize::serialize(
+        &token,
+        Serializer::new(&mut urlencoder).charset(charset),
+    )
+    .unwrap();
+    assert_eq!(
+        urlencoder.finish(),
+        "id=%00%FF%C3a+b&tags%5B%5D=%E9&text=caf%E9"
+    );
+
+    #[derive(serde_derive::Deserialize, Debug, PartialEq)]
+    struct Id {
+        #[serde(with = "serde_bytes")]
+        id: Vec<u8>,
+    }
+    assert_eq!(
+        serde_urlencoded::from_str::<Id>(&encoded).unwrap().id,
+        token.id
+    );
+}
+
+#[test]
+fn serialize_spaces() {
+    let params = &[("a b", "1 + 1"), ("c", "%20+")];
+    let output = serde_urlencoded::to_string(params).unwrap();
+    assert_eq!(output, "a+b=1+%2B+1&c=%2520%2B");
+    assert_eq!(
+        serde_urlencoded::to_string_with_spaces(params, SpaceEncoding::Plus),
+        Ok(output)
+    );
+
+    let output =
+        serde_urlencoded::to_string_with_spaces(params, SpaceEncoding::Percent)
+            .unwrap();
+    assert_eq!(output, "a%20b=1%20%2B%201&c=%2520%2B");
+
+    let de = serde_urlencoded::Deserializer::from_bytes(output.as_bytes())
+        .spaces(SpaceEncoding::Percent);
+    assert_eq!(
+        <Vec<(String, String)> as serde::Deserialize>::deserialize(de),
+        Ok(vec![
+            ("a b".to_owned(), "1 + 1".to_owned()),
+            ("c".to_owned(), "%20+".to_owned()),
+        ])
+    );
+
+    #[derive(Serialize)]
+    struct Token<'a> {
+        #[serde(with = "serde_bytes")]
+        id: &'a [u8],
+    }
+    assert_eq!(
+        serde_urlencoded::to_string_with_spaces(
+            Token { id: b"a b+" },
+            SpaceEncoding::Percent
+        ),
+        Ok("id=a%20b%2B".to_owned())
+    );
+}
+
+#[test]
+fn serialize_data_enum() {
+    #[derive(Serialize, serde_derive::Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Point(i32, i32),
+        Rect { width: u32, height: u32 },
+    }
+
+    #[derive(Serialize, serde_derive::Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        name: String,
+        shape: Shape,
+        layers: Vec<Shape>,
+    }
+
+    let drawing = Drawing {
+        name: "logo".to_owned(),
+        shape: Shape::Rect {
+            width: 4,
+            height: 2,
+        },
+        layers: vec![Shape::Empty, Shape::Circle(3), Shape::Point(1, -2)],
+    };
+    let encoded = to_string_with_style(&drawing, ArrayStyle::Indexed).unwrap();
+    assert_eq!(
+        encoded,
+        "name=logo&shape%5Brect%5D%5Bwidth%5D=4\
+         &shape%5Brect%5D%5Bheight%5D=2&layers%5B0%5D=empty\
+         &layers%5B1%5D%5Bcircle%5D=3&layers%5B2%5D%5Bpoint%5D%5B0%5D=1\
+         &layers%5B2%5D%5Bpoint%5D%5B1%5D=-2"
+    );
+    assert_eq!(serde_urlencoded::from_str(&encoded), Ok(drawing));
+
+    // Unit variants and variants with fields cannot be told apart once
+    // written without indices.
+    let mixed = &[("layers", vec![Shape::Circle(3), Shape::Empty])];
+    for &style in &[
+        ArrayStyle::Brackets,
+        ArrayStyle::Repeat,
+        ArrayStyle::Delimited(','),
+    ] {
+        let err = to_string_with_style(mixed, style).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert_eq!(err.path(), Some("layers[1]"));
+    }
+    let units = &[("layers", vec![Shape::Empty, Shape::Empty])];
+    assert_eq!(
+        serde_urlencoded::to_string(units),
+        Ok("layers%5B%5D=empty&layers%5B%5D=empty".to_owned())
+    );
+
+    let params = &[("shape", Shape::Circle(3))];
+    assert_eq!(
+        serde_urlencoded::to_string(params),
+        Ok("shape%5Bcircle%5D=3".to_owned())
+    );
+}
+
+#[test]
+fn serialize_top_level_enum() {
+    #[derive(Serialize)]
+    struct Push {
+        branch: &'static str,
+        commits: u32,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Event {
+        Ping,
+        Push(Push),
+        Star { repo: &'static str },
+        Tag(&'static str, u32),
+    }
+
+    let push = Event::Push(Push {
+        branch: "main",
+        commits: 2,
+    });
+    assert_eq!(
+        serde_urlencoded::to_string(push),
+        Ok("event=push&branch=main&commits=2".to_owned())
+    );
+    assert_eq!(
+        serde_urlencoded::to_string(Event::Star { repo: "serde" }),
+        Ok("event=star&repo=serde".to_owned())
+    );
+    assert_eq!(
+        serde_urlencoded::to_string(Event::Ping),
+        Ok("event=ping".to_owned())
+    );
+    assert_matches!(
+        serde_urlencoded::to_string(Event::Tag("v1", 1)),
+        Err(ref err) if err.kind() == ErrorKind::TopLevel
+    );
+
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    let serializer = Serializer::new(&mut urlencoder).enum_tag("kind");
+    serde::Serialize::serialize(&Event::Ping, serializer).unwrap();
+    assert_eq!(urlencoder.finish(), "kind=ping");
+}
+
+#[test]
+fn serialize_variant_indices() {
+    #[derive(Serialize)]
+    enum Status {
+        Draft,
+        Published,
+        Archived,
+    }
+
+    #[derive(Serialize)]
+    struct Post {
+        status: Status,
+        history: Vec<Status>,
+    }
+
+    let post = Post {
+        status: Status::Archived,
+        history: vec![Status::Draft, Status::Published],
+    };
+
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    let serializer = Serializer::new(&mut urlencoder).variant_indices(true);
+    serde::Serialize::serialize(&post, serializer).unwrap();
+    assert_eq!(
+        urlencoder.finish(),
+        "status=2&history%5B%5D=0&history%5B%5D=1"
+    );
+
+    let mut urlencoder = form_urlencoded::Serializer::new(String::new());
+    let serializer = Serializer::new(&mut urlencoder)
+        .array_style(ArrayStyle::Delimited(','))
+        .variant_indices(true);
+    serde::Serialize::serialize(&post, serializer).unwrap();
+    assert_eq!(urlencoder.finish(), "status=2&history=0%2C1");
+
+    assert_eq!(
+        serde_urlencoded::to_string(&post),
+        Ok(
+            "status=Archived&history%5B%5D=Draft&history%5B%5D=Published"
+                .to_owned()
+        )
+    );
+}